@@ -0,0 +1,50 @@
+// Packs every file in programs/ into the kernel as the disk's initial contents.
+//
+// Generates $OUT_DIR/initial_files.rs defining INITIAL_FILES, a list of
+// (name, contents) pairs in name order, each embedded with include_bytes!.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[path = "src/limits.rs"]
+#[allow(dead_code)]
+mod limits;
+
+use limits::{MAX_FILENAME_BYTES, MAX_FILES_STORED, MAX_FILE_BYTES, RESERVED_FILES};
+
+const MAX_INITIAL_FILES: usize = MAX_FILES_STORED - RESERVED_FILES;
+
+fn main() {
+    let programs = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("programs");
+    println!("cargo:rerun-if-changed={}", programs.display());
+    println!("cargo:rerun-if-changed=src/limits.rs");
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&programs).expect("programs/ directory is missing") {
+        let entry = entry.unwrap();
+        let name = entry.file_name().into_string().expect("program names must be UTF-8");
+        if name.starts_with('.') || !entry.file_type().unwrap().is_file() {
+            continue;
+        }
+        println!("cargo:rerun-if-changed={}", entry.path().display());
+        if name.len() > MAX_FILENAME_BYTES || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            panic!("programs/{name}: names are 1 to {MAX_FILENAME_BYTES} letters, digits or underscores");
+        }
+        if entry.metadata().unwrap().len() > MAX_FILE_BYTES as u64 {
+            panic!("programs/{name} is larger than {MAX_FILE_BYTES} bytes");
+        }
+        files.push((name, entry.path()));
+    }
+    if files.len() > MAX_INITIAL_FILES {
+        panic!("programs/ holds {} files; the disk has room for {MAX_INITIAL_FILES}", files.len());
+    }
+    files.sort();
+
+    let mut generated = String::from("const INITIAL_FILES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in &files {
+        generated += &format!("    ({name:?}, include_bytes!({:?})),\n", path.display().to_string());
+    }
+    generated += "];\n";
+    fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("initial_files.rs"), generated).unwrap();
+}
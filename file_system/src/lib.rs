@@ -0,0 +1,505 @@
+// The block file system the kernel keeps its files on, and the diskimg tool
+// reads and writes images of.
+//
+// Layout, in blocks of BLOCK_SIZE bytes:
+//
+//     0                      superblock: a magic number and the sizes below
+//     1 ..                   block bitmap, one bit per block, set when in use
+//     .. ..                  inode table, one inode per file slot
+//     ..  NUM_BLOCKS         file data
+//
+// An inode holds its file's name, length and the numbers of its data blocks, so
+// the inode table doubles as the single flat directory. A slot whose name starts
+// with a zero byte is free. Names are zero-padded to MAX_FILENAME_BYTES; callers
+// may pass them padded or not.
+//
+// FileSystem::new formats the device it is given. FileSystem::mount takes over a
+// device that already holds a file system with the same sizes and leaves its
+// files in place.
+
+#![no_std]
+
+const MAGIC: [u8; 8] = *b"SWIMFS01";
+const SUPERBLOCK: usize = 0;
+const FIRST_BITMAP_BLOCK: usize = 1;
+
+// Block numbers are stored as two bytes, so NUM_BLOCKS may not pass this.
+const MAX_BLOCKS: usize = u16::MAX as usize + 1;
+
+// A disk the file system can sit on, addressed in whole blocks.
+pub trait BlockDevice<const BLOCK_SIZE: usize>{
+    fn read(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]);
+    fn write(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]);
+}
+
+// Blocks kept in memory, lost when the machine stops.
+pub struct RamDisk<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize>{
+    blocks: [[u8; BLOCK_SIZE]; NUM_BLOCKS],
+}
+
+impl<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize> RamDisk<BLOCK_SIZE, NUM_BLOCKS>{
+    pub fn new() -> Self{
+        Self{blocks: [[0; BLOCK_SIZE]; NUM_BLOCKS]}
+    }
+}
+
+impl<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize> Default for RamDisk<BLOCK_SIZE, NUM_BLOCKS>{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize> BlockDevice<BLOCK_SIZE> for RamDisk<BLOCK_SIZE, NUM_BLOCKS>{
+    fn read(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]){
+        buffer.copy_from_slice(&self.blocks[block]);
+    }
+
+    fn write(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]){
+        self.blocks[block].copy_from_slice(buffer);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileSystemError{
+    FileNotFound,
+    FileNotOpen,
+    NotOpenForRead,
+    NotOpenForWrite,
+    TooManyOpen,
+    TooManyFiles,
+    AlreadyOpen,
+    AlreadyExists,
+    DiskFull,
+    FileTooBig,
+    FilenameTooLong,
+    EmptyFilename,
+    InvalidFileDescriptor,
+    NotFormatted,
+}
+
+pub type FileSystemResult<T> = Result<T, FileSystemError>;
+
+#[derive(Copy, Clone)]
+struct OpenFile{
+    inode: usize,
+    offset: usize,
+    writing: bool,
+}
+
+#[derive(Copy, Clone)]
+struct Inode<const MAX_FILE_BLOCKS: usize, const MAX_FILENAME_BYTES: usize>{
+    name: [u8; MAX_FILENAME_BYTES],
+    size: usize,
+    blocks: [u16; MAX_FILE_BLOCKS],
+}
+
+impl<const MAX_FILE_BLOCKS: usize, const MAX_FILENAME_BYTES: usize> Inode<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>{
+    const BYTES: usize = MAX_FILENAME_BYTES + 4 + 2 * MAX_FILE_BLOCKS;
+
+    fn empty() -> Self{
+        Self{name: [0; MAX_FILENAME_BYTES], size: 0, blocks: [0; MAX_FILE_BLOCKS]}
+    }
+
+    fn in_use(&self) -> bool{
+        self.name[0] != 0
+    }
+
+    fn num_blocks(&self, block_size: usize) -> usize{
+        self.size.div_ceil(block_size)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self{
+        let mut inode = Self::empty();
+        inode.name.copy_from_slice(&bytes[..MAX_FILENAME_BYTES]);
+        let size = &bytes[MAX_FILENAME_BYTES..MAX_FILENAME_BYTES+4];
+        inode.size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        for (i, block) in inode.blocks.iter_mut().enumerate(){
+            let at = MAX_FILENAME_BYTES + 4 + 2 * i;
+            *block = u16::from_le_bytes([bytes[at], bytes[at+1]]);
+        }
+        inode
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]){
+        bytes[..MAX_FILENAME_BYTES].copy_from_slice(&self.name);
+        bytes[MAX_FILENAME_BYTES..MAX_FILENAME_BYTES+4].copy_from_slice(&(self.size as u32).to_le_bytes());
+        for (i, block) in self.blocks.iter().enumerate(){
+            let at = MAX_FILENAME_BYTES + 4 + 2 * i;
+            bytes[at..at+2].copy_from_slice(&block.to_le_bytes());
+        }
+    }
+}
+
+pub struct FileSystem<
+    const MAX_OPEN: usize,
+    const BLOCK_SIZE: usize,
+    const NUM_BLOCKS: usize,
+    const MAX_FILE_BLOCKS: usize,
+    const MAX_FILE_BYTES: usize,
+    const MAX_FILES_STORED: usize,
+    const MAX_FILENAME_BYTES: usize,
+    D: BlockDevice<BLOCK_SIZE>,
+>{
+    disk: D,
+    open: [Option<OpenFile>; MAX_OPEN],
+}
+
+impl<
+    const MAX_OPEN: usize,
+    const BLOCK_SIZE: usize,
+    const NUM_BLOCKS: usize,
+    const MAX_FILE_BLOCKS: usize,
+    const MAX_FILE_BYTES: usize,
+    const MAX_FILES_STORED: usize,
+    const MAX_FILENAME_BYTES: usize,
+    D: BlockDevice<BLOCK_SIZE>,
+> FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES, D>{
+    const INODES_PER_BLOCK: usize = BLOCK_SIZE / Inode::<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>::BYTES;
+    const BITMAP_BLOCKS: usize = NUM_BLOCKS.div_ceil(8 * BLOCK_SIZE);
+    const FIRST_INODE_BLOCK: usize = FIRST_BITMAP_BLOCK + Self::BITMAP_BLOCKS;
+    const FIRST_DATA_BLOCK: usize = Self::FIRST_INODE_BLOCK + MAX_FILES_STORED.div_ceil(Self::INODES_PER_BLOCK);
+    // The most a file may hold: its block list full, or MAX_FILE_BYTES if smaller.
+    const FILE_CAPACITY: usize = if MAX_FILE_BYTES < MAX_FILE_BLOCKS * BLOCK_SIZE { MAX_FILE_BYTES } else { MAX_FILE_BLOCKS * BLOCK_SIZE };
+    const SIZES_FIT: () = {
+        assert!(BLOCK_SIZE >= MAGIC.len() + 5 * 4, "the superblock does not fit in a block");
+        assert!(Inode::<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>::BYTES <= BLOCK_SIZE, "an inode does not fit in a block");
+        assert!(NUM_BLOCKS <= MAX_BLOCKS, "block numbers do not fit in two bytes");
+        assert!(MAX_FILENAME_BYTES > 0, "names need at least one byte");
+    };
+
+    // Format the device: an empty inode table and every data block free.
+    pub fn new(disk: D) -> Self{
+        let () = Self::SIZES_FIT;
+        assert!(Self::FIRST_DATA_BLOCK < NUM_BLOCKS, "the disk has no room for data");
+        let mut fs = Self{disk, open: [None; MAX_OPEN]};
+        let mut block = [0; BLOCK_SIZE];
+        for b in FIRST_BITMAP_BLOCK..Self::FIRST_DATA_BLOCK{
+            fs.disk.write(b, &block);
+        }
+        for b in 0..Self::FIRST_DATA_BLOCK{
+            fs.set_block_used(b, true);
+        }
+        Self::superblock(&mut block);
+        fs.disk.write(SUPERBLOCK, &block);
+        fs
+    }
+
+    // Take over a device formatted with these sizes, keeping the files on it.
+    pub fn mount(disk: D) -> FileSystemResult<Self>{
+        let () = Self::SIZES_FIT;
+        let mut expected = [0; BLOCK_SIZE];
+        Self::superblock(&mut expected);
+        let mut found = [0; BLOCK_SIZE];
+        disk.read(SUPERBLOCK, &mut found);
+        if found != expected{
+            return Err(FileSystemError::NotFormatted);
+        }
+        Ok(Self{disk, open: [None; MAX_OPEN]})
+    }
+
+    fn superblock(block: &mut [u8; BLOCK_SIZE]){
+        block.fill(0);
+        block[..MAGIC.len()].copy_from_slice(&MAGIC);
+        let sizes = [BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILES_STORED, MAX_FILENAME_BYTES];
+        for (i, size) in sizes.iter().enumerate(){
+            let at = MAGIC.len() + 4 * i;
+            block[at..at+4].copy_from_slice(&(*size as u32).to_le_bytes());
+        }
+    }
+
+    pub fn open_read(&mut self, filename: &str) -> FileSystemResult<usize>{
+        let inode = self.find(filename)?;
+        if self.open.iter().flatten().any(|f| f.inode == inode && f.writing){
+            return Err(FileSystemError::AlreadyOpen);
+        }
+        self.open_fd(OpenFile{inode, offset: 0, writing: false})
+    }
+
+    // Open a file for writing from its start, creating it if it is missing and
+    // emptying it if it is not.
+    pub fn open_create(&mut self, filename: &str) -> FileSystemResult<usize>{
+        let name = Self::padded(filename)?;
+        if !self.open.iter().any(|f| f.is_none()){
+            return Err(FileSystemError::TooManyOpen);
+        }
+        let inode = match self.find(filename){
+            Ok(inode) => {
+                if self.is_open(inode){
+                    return Err(FileSystemError::AlreadyOpen);
+                }
+                self.resize(inode, 0)?;
+                inode
+            }
+            Err(_) => self.create(name)?,
+        };
+        self.open_fd(OpenFile{inode, offset: 0, writing: true})
+    }
+
+    pub fn close(&mut self, fd: usize) -> FileSystemResult<()>{
+        match self.open.get_mut(fd){
+            Some(slot @ Some(_)) => {
+                *slot = None;
+                Ok(())
+            }
+            Some(None) => Err(FileSystemError::FileNotOpen),
+            None => Err(FileSystemError::InvalidFileDescriptor),
+        }
+    }
+
+    // Read from where the last read stopped, returning how many bytes were read;
+    // 0 at the end of the file.
+    pub fn read(&mut self, fd: usize, buffer: &mut [u8]) -> FileSystemResult<usize>{
+        let file = self.open_file(fd)?;
+        if file.writing{
+            return Err(FileSystemError::NotOpenForRead);
+        }
+        let inode = self.inode(file.inode);
+        let len = buffer.len().min(inode.size - file.offset.min(inode.size));
+        let mut block = [0; BLOCK_SIZE];
+        let mut done = 0;
+        while done < len{
+            let at = file.offset + done;
+            let start = at % BLOCK_SIZE;
+            let count = (BLOCK_SIZE - start).min(len - done);
+            self.disk.read(inode.blocks[at / BLOCK_SIZE] as usize, &mut block);
+            buffer[done..done+count].copy_from_slice(&block[start..start+count]);
+            done += count;
+        }
+        self.open[fd] = Some(OpenFile{offset: file.offset + len, ..file});
+        Ok(len)
+    }
+
+    // Append to a file opened with open_create. Nothing is written unless all of
+    // buffer fits in the file and on the disk.
+    pub fn write(&mut self, fd: usize, buffer: &[u8]) -> FileSystemResult<()>{
+        let file = self.open_file(fd)?;
+        if !file.writing{
+            return Err(FileSystemError::NotOpenForWrite);
+        }
+        let end = file.offset + buffer.len();
+        self.resize(file.inode, end)?;
+        self.write_at(file.inode, file.offset, buffer);
+        self.open[fd] = Some(OpenFile{offset: end, ..file});
+        Ok(())
+    }
+
+    // Replace a file's contents in place, creating it if it is missing. The file
+    // keeps its blocks and gains or gives back only the difference, and nothing is
+    // written unless the new contents fit, so a failed save leaves the old file.
+    pub fn replace(&mut self, filename: &str, contents: &[u8]) -> FileSystemResult<()>{
+        let inode = match self.find(filename){
+            Ok(inode) => {
+                if self.is_open(inode){
+                    return Err(FileSystemError::AlreadyOpen);
+                }
+                inode
+            }
+            Err(FileSystemError::FileNotFound) => {
+                if contents.len() > Self::FILE_CAPACITY{
+                    return Err(FileSystemError::FileTooBig);
+                }
+                if contents.len().div_ceil(BLOCK_SIZE) > self.num_free_blocks(){
+                    return Err(FileSystemError::DiskFull);
+                }
+                self.create(Self::padded(filename)?)?
+            }
+            Err(e) => return Err(e),
+        };
+        self.resize(inode, contents.len())?;
+        self.write_at(inode, 0, contents);
+        Ok(())
+    }
+
+    pub fn unlink(&mut self, filename: &str) -> FileSystemResult<()>{
+        let inode = self.find(filename)?;
+        if self.is_open(inode){
+            return Err(FileSystemError::AlreadyOpen);
+        }
+        self.resize(inode, 0)?;
+        self.set_inode(inode, &Inode::empty());
+        Ok(())
+    }
+
+    // Give a file a new name; its inode and blocks stay where they are.
+    pub fn rename(&mut self, from: &str, to: &str) -> FileSystemResult<()>{
+        let name = Self::padded(to)?;
+        let inode = self.find(from)?;
+        if self.find(to).is_ok(){
+            return Err(FileSystemError::AlreadyExists);
+        }
+        let mut record = self.inode(inode);
+        record.name = name;
+        self.set_inode(inode, &record);
+        Ok(())
+    }
+
+    pub fn file_size(&mut self, filename: &str) -> FileSystemResult<usize>{
+        let inode = self.find(filename)?;
+        Ok(self.inode(inode).size)
+    }
+
+    // How many files there are and their zero-padded names, in inode order.
+    pub fn list_directory(&mut self) -> FileSystemResult<(usize, [[u8; MAX_FILENAME_BYTES]; MAX_FILES_STORED])>{
+        let mut names = [[0; MAX_FILENAME_BYTES]; MAX_FILES_STORED];
+        let mut count = 0;
+        for i in 0..MAX_FILES_STORED{
+            let inode = self.inode(i);
+            if inode.in_use(){
+                names[count] = inode.name;
+                count +=1;
+            }
+        }
+        Ok((count, names))
+    }
+
+    pub fn num_free_blocks(&self) -> usize{
+        let mut block = [0; BLOCK_SIZE];
+        let mut free = 0;
+        for b in Self::FIRST_DATA_BLOCK..NUM_BLOCKS{
+            if b == Self::FIRST_DATA_BLOCK || b % (8 * BLOCK_SIZE) == 0{
+                self.disk.read(FIRST_BITMAP_BLOCK + b / (8 * BLOCK_SIZE), &mut block);
+            }
+            let bit = b % (8 * BLOCK_SIZE);
+            if block[bit / 8] & (1 << (bit % 8)) == 0{
+                free +=1;
+            }
+        }
+        free
+    }
+
+    fn padded(filename: &str) -> FileSystemResult<[u8; MAX_FILENAME_BYTES]>{
+        let filename = filename.trim_end_matches('\0');
+        if filename.is_empty(){
+            return Err(FileSystemError::EmptyFilename);
+        }
+        if filename.len() > MAX_FILENAME_BYTES{
+            return Err(FileSystemError::FilenameTooLong);
+        }
+        let mut name = [0; MAX_FILENAME_BYTES];
+        name[..filename.len()].copy_from_slice(filename.as_bytes());
+        Ok(name)
+    }
+
+    fn find(&self, filename: &str) -> FileSystemResult<usize>{
+        let name = Self::padded(filename)?;
+        (0..MAX_FILES_STORED).find(|i| self.inode(*i).name == name).ok_or(FileSystemError::FileNotFound)
+    }
+
+    fn create(&mut self, name: [u8; MAX_FILENAME_BYTES]) -> FileSystemResult<usize>{
+        let inode = (0..MAX_FILES_STORED).find(|i| !self.inode(*i).in_use()).ok_or(FileSystemError::TooManyFiles)?;
+        let mut record = Inode::empty();
+        record.name = name;
+        self.set_inode(inode, &record);
+        Ok(inode)
+    }
+
+    fn is_open(&self, inode: usize) -> bool{
+        self.open.iter().flatten().any(|f| f.inode == inode)
+    }
+
+    fn open_fd(&mut self, file: OpenFile) -> FileSystemResult<usize>{
+        let fd = self.open.iter().position(|f| f.is_none()).ok_or(FileSystemError::TooManyOpen)?;
+        self.open[fd] = Some(file);
+        Ok(fd)
+    }
+
+    fn open_file(&self, fd: usize) -> FileSystemResult<OpenFile>{
+        match self.open.get(fd){
+            Some(Some(file)) => Ok(*file),
+            Some(None) => Err(FileSystemError::FileNotOpen),
+            None => Err(FileSystemError::InvalidFileDescriptor),
+        }
+    }
+
+    // Give a file exactly the blocks size bytes need, checking for room first so a
+    // failure changes nothing.
+    fn resize(&mut self, inode: usize, size: usize) -> FileSystemResult<()>{
+        if size > Self::FILE_CAPACITY{
+            return Err(FileSystemError::FileTooBig);
+        }
+        let mut record = self.inode(inode);
+        let (have, need) = (record.num_blocks(BLOCK_SIZE), size.div_ceil(BLOCK_SIZE));
+        if need > have && need - have > self.num_free_blocks(){
+            return Err(FileSystemError::DiskFull);
+        }
+        for i in need..have{
+            self.set_block_used(record.blocks[i] as usize, false);
+            record.blocks[i] = 0;
+        }
+        for i in have..need{
+            let block = self.allocate_block().ok_or(FileSystemError::DiskFull)?;
+            record.blocks[i] = block as u16;
+        }
+        record.size = size;
+        self.set_inode(inode, &record);
+        Ok(())
+    }
+
+    // Copy bytes into a file's blocks from offset on; the blocks must be there.
+    fn write_at(&mut self, inode: usize, offset: usize, bytes: &[u8]){
+        let record = self.inode(inode);
+        let mut block = [0; BLOCK_SIZE];
+        let mut done = 0;
+        while done < bytes.len(){
+            let at = offset + done;
+            let start = at % BLOCK_SIZE;
+            let count = (BLOCK_SIZE - start).min(bytes.len() - done);
+            let number = record.blocks[at / BLOCK_SIZE] as usize;
+            if count < BLOCK_SIZE{
+                self.disk.read(number, &mut block);
+            }
+            block[start..start+count].copy_from_slice(&bytes[done..done+count]);
+            self.disk.write(number, &block);
+            done += count;
+        }
+    }
+
+    fn inode(&self, inode: usize) -> Inode<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>{
+        let (block_num, at) = Self::inode_place(inode);
+        let mut block = [0; BLOCK_SIZE];
+        self.disk.read(block_num, &mut block);
+        Inode::from_bytes(&block[at..at+Inode::<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>::BYTES])
+    }
+
+    fn set_inode(&mut self, inode: usize, record: &Inode<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>){
+        let (block_num, at) = Self::inode_place(inode);
+        let mut block = [0; BLOCK_SIZE];
+        self.disk.read(block_num, &mut block);
+        record.write_bytes(&mut block[at..at+Inode::<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>::BYTES]);
+        self.disk.write(block_num, &block);
+    }
+
+    fn inode_place(inode: usize) -> (usize, usize){
+        let bytes = Inode::<MAX_FILE_BLOCKS, MAX_FILENAME_BYTES>::BYTES;
+        (Self::FIRST_INODE_BLOCK + inode / Self::INODES_PER_BLOCK, inode % Self::INODES_PER_BLOCK * bytes)
+    }
+
+    fn allocate_block(&mut self) -> Option<usize>{
+        let mut block = [0; BLOCK_SIZE];
+        for b in Self::FIRST_DATA_BLOCK..NUM_BLOCKS{
+            if b == Self::FIRST_DATA_BLOCK || b % (8 * BLOCK_SIZE) == 0{
+                self.disk.read(FIRST_BITMAP_BLOCK + b / (8 * BLOCK_SIZE), &mut block);
+            }
+            let bit = b % (8 * BLOCK_SIZE);
+            if block[bit / 8] & (1 << (bit % 8)) == 0{
+                self.set_block_used(b, true);
+                return Some(b);
+            }
+        }
+        None
+    }
+
+    fn set_block_used(&mut self, b: usize, used: bool){
+        let bitmap_block = FIRST_BITMAP_BLOCK + b / (8 * BLOCK_SIZE);
+        let bit = b % (8 * BLOCK_SIZE);
+        let mut block = [0; BLOCK_SIZE];
+        self.disk.read(bitmap_block, &mut block);
+        if used{
+            block[bit / 8] |= 1 << (bit % 8);
+        }
+        else{
+            block[bit / 8] &= !(1 << (bit % 8));
+        }
+        self.disk.write(bitmap_block, &block);
+    }
+}
@@ -0,0 +1,77 @@
+// Kernel heap, mapped from the usable frames in the bootloader's memory map.
+//
+// The bootloader maps all physical memory at an offset (its map_physical_memory
+// feature), which lets us walk and extend the active page table directly.
+
+use bootloader::bootinfo::{BootInfo, MemoryMap, MemoryRegionType};
+use linked_list_allocator::LockedHeap;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_SIZE: usize = 1024 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+// Must run once, before anything allocates. On failure nothing can be allocated,
+// so the caller reports the error and stops.
+pub fn init_heap(boot_info: &'static BootInfo) -> Result<(), MapToError<Size4KiB>>{
+    let offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { active_page_table(offset) };
+    let mut frames = BootInfoFrameAllocator{memory_map: &boot_info.memory_map, region: 0, next: 0};
+    map_heap(&mut mapper, &mut frames)?;
+    unsafe {
+        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+    Ok(())
+}
+
+// The caller guarantees all physical memory is mapped at offset, and calls this
+// only once so the returned table is not aliased.
+unsafe fn active_page_table(offset: VirtAddr) -> OffsetPageTable<'static>{
+    let (level_4_frame, _) = Cr3::read();
+    let table: *mut PageTable = (offset + level_4_frame.start_address().as_u64()).as_mut_ptr();
+    OffsetPageTable::new(&mut *table, offset)
+}
+
+fn map_heap(mapper: &mut impl Mapper<Size4KiB>, frames: &mut impl FrameAllocator<Size4KiB>) -> Result<(), MapToError<Size4KiB>>{
+    let start = VirtAddr::new(HEAP_START as u64);
+    let end = start + HEAP_SIZE as u64 - 1u64;
+    let pages = Page::range_inclusive(Page::containing_address(start), Page::containing_address(end));
+    for page in pages{
+        let frame = frames.allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe {
+            mapper.map_to(page, frame, flags, frames)?.flush();
+        }
+    }
+    Ok(())
+}
+
+const FRAME_SIZE: u64 = 4096;
+
+// Hands out the usable frames in order; frames are never freed. region indexes
+// the memory map and next is the address of the next frame to hand out, so each
+// allocation resumes where the last one stopped.
+struct BootInfoFrameAllocator{
+    memory_map: &'static MemoryMap,
+    region: usize,
+    next: u64,
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator{
+    fn allocate_frame(&mut self) -> Option<PhysFrame>{
+        while let Some(region) = self.memory_map.get(self.region){
+            let address = self.next.max(region.range.start_addr());
+            if region.region_type == MemoryRegionType::Usable && address < region.range.end_addr(){
+                self.next = address + FRAME_SIZE;
+                return Some(PhysFrame::containing_address(PhysAddr::new(address)));
+            }
+            self.region +=1;
+        }
+        None
+    }
+}
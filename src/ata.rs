@@ -0,0 +1,227 @@
+// ATA PIO driver for a disk on the primary IDE bus.
+//
+// Blocks are read and written through the same calls as a RamDisk, one 512-byte
+// sector at a time by polling the status port. A block smaller than a sector
+// shares it with its neighbours, so writing one reads the sector back first.
+// The boot image is the primary master under QEMU, so the kernel keeps its
+// files on the primary slave.
+
+use x86_64::instructions::port::Port;
+
+pub const SECTOR_BYTES: usize = 512;
+
+const DATA: u16 = 0x1F0;
+const SECTOR_COUNT: u16 = 0x1F2;
+const LBA_LOW: u16 = 0x1F3;
+const LBA_MID: u16 = 0x1F4;
+const LBA_HIGH: u16 = 0x1F5;
+const DRIVE_SELECT: u16 = 0x1F6;
+const COMMAND: u16 = 0x1F7;
+const ALT_STATUS: u16 = 0x3F6;
+
+const STATUS_ERR: u8 = 0x01;
+const STATUS_DRQ: u8 = 0x08;
+const STATUS_DF: u8 = 0x20;
+const STATUS_BSY: u8 = 0x80;
+
+const CMD_READ: u8 = 0x20;
+const CMD_WRITE: u8 = 0x30;
+const CMD_FLUSH: u8 = 0xE7;
+const CMD_IDENTIFY: u8 = 0xEC;
+
+const POLL_LIMIT: usize = 1_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Drive{
+    Master,
+    Slave,
+}
+
+impl Drive{
+    fn select(&self) -> u8{
+        match self{
+            Drive::Master => 0xE0,
+            Drive::Slave => 0xF0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtaError{
+    OutOfRange,
+    DeviceFault,
+    Timeout,
+}
+
+// Only names the drive, so copies all drive the same disk.
+#[derive(Clone, Copy)]
+pub struct AtaDisk<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize>{
+    drive: Drive,
+}
+
+impl<const BLOCK_SIZE: usize, const NUM_BLOCKS: usize> AtaDisk<BLOCK_SIZE, NUM_BLOCKS>{
+
+    // None when no ATA disk answers on that drive.
+    pub fn new(drive: Drive) -> Option<Self>{
+        let disk = Self{drive};
+        if disk.identify(){
+            Some(disk)
+        }
+        else{
+            None
+        }
+    }
+
+    pub fn num_blocks(&self) -> usize{
+        NUM_BLOCKS
+    }
+
+    pub fn read(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]) -> Result<(), AtaError>{
+        if block >= NUM_BLOCKS{
+            return Err(AtaError::OutOfRange);
+        }
+        let mut sector = [0; SECTOR_BYTES];
+        let mut done = 0;
+        while done < BLOCK_SIZE{
+            let (lba, start, len) = Self::span(block, done);
+            self.read_sector(lba, &mut sector)?;
+            buffer[done..done+len].copy_from_slice(&sector[start..start+len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    // Writes reach the drive's cache; flush makes them stick.
+    pub fn write(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]) -> Result<(), AtaError>{
+        if block >= NUM_BLOCKS{
+            return Err(AtaError::OutOfRange);
+        }
+        let mut sector = [0; SECTOR_BYTES];
+        let mut done = 0;
+        while done < BLOCK_SIZE{
+            let (lba, start, len) = Self::span(block, done);
+            if len < SECTOR_BYTES{
+                self.read_sector(lba, &mut sector)?;
+            }
+            sector[start..start+len].copy_from_slice(&buffer[done..done+len]);
+            self.write_sector(lba, &sector)?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), AtaError>{
+        self.wait_idle()?;
+        outb(DRIVE_SELECT, self.drive.select());
+        self.command(CMD_FLUSH);
+        self.wait_idle()
+    }
+
+    // The sector holding byte done of a block, where that byte sits in the
+    // sector, and how much of the block the sector covers from there.
+    fn span(block: usize, done: usize) -> (usize, usize, usize){
+        let offset = block * BLOCK_SIZE + done;
+        let start = offset % SECTOR_BYTES;
+        (offset / SECTOR_BYTES, start, (SECTOR_BYTES - start).min(BLOCK_SIZE - done))
+    }
+
+    // IDENTIFY leaves the status at zero when nothing is attached, and sets the
+    // LBA ports for ATAPI devices, which this driver does not handle.
+    fn identify(&self) -> bool{
+        outb(DRIVE_SELECT, self.drive.select() & 0xF0);
+        for port in [SECTOR_COUNT, LBA_LOW, LBA_MID, LBA_HIGH]{
+            outb(port, 0);
+        }
+        self.command(CMD_IDENTIFY);
+        if inb(COMMAND) == 0 || self.wait_idle().is_err(){
+            return false;
+        }
+        if inb(LBA_MID) != 0 || inb(LBA_HIGH) != 0 || self.wait_data().is_err(){
+            return false;
+        }
+        for _ in 0..SECTOR_BYTES / 2{
+            inw(DATA);
+        }
+        true
+    }
+
+    fn read_sector(&self, lba: usize, sector: &mut [u8]) -> Result<(), AtaError>{
+        self.seek(lba)?;
+        self.command(CMD_READ);
+        self.wait_data()?;
+        for pair in sector.chunks_exact_mut(2){
+            let word = inw(DATA);
+            pair[0] = word as u8;
+            pair[1] = (word >> 8) as u8;
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: usize, sector: &[u8]) -> Result<(), AtaError>{
+        self.seek(lba)?;
+        self.command(CMD_WRITE);
+        self.wait_data()?;
+        for pair in sector.chunks_exact(2){
+            outw(DATA, pair[0] as u16 | (pair[1] as u16) << 8);
+        }
+        self.wait_idle()
+    }
+
+    // Select the drive and a single-sector transfer at a 28-bit LBA.
+    fn seek(&self, lba: usize) -> Result<(), AtaError>{
+        self.wait_idle()?;
+        outb(DRIVE_SELECT, self.drive.select() | ((lba >> 24) & 0x0F) as u8);
+        outb(SECTOR_COUNT, 1);
+        outb(LBA_LOW, lba as u8);
+        outb(LBA_MID, (lba >> 8) as u8);
+        outb(LBA_HIGH, (lba >> 16) as u8);
+        Ok(())
+    }
+
+    // Reading the alternate status four times gives the drive the 400ns it needs
+    // after a command before its status is meaningful.
+    fn command(&self, command: u8){
+        outb(COMMAND, command);
+        for _ in 0..4{
+            inb(ALT_STATUS);
+        }
+    }
+
+    fn wait_idle(&self) -> Result<(), AtaError>{
+        for _ in 0..POLL_LIMIT{
+            if inb(COMMAND) & STATUS_BSY == 0{
+                return Ok(());
+            }
+        }
+        Err(AtaError::Timeout)
+    }
+
+    fn wait_data(&self) -> Result<(), AtaError>{
+        for _ in 0..POLL_LIMIT{
+            let status = inb(COMMAND);
+            if status & (STATUS_ERR | STATUS_DF) != 0{
+                return Err(AtaError::DeviceFault);
+            }
+            if status & STATUS_BSY == 0 && status & STATUS_DRQ != 0{
+                return Ok(());
+            }
+        }
+        Err(AtaError::Timeout)
+    }
+}
+
+fn inb(port: u16) -> u8{
+    unsafe { Port::new(port).read() }
+}
+
+fn outb(port: u16, value: u8){
+    unsafe { Port::new(port).write(value) }
+}
+
+fn inw(port: u16) -> u16{
+    unsafe { Port::new(port).read() }
+}
+
+fn outw(port: u16, value: u16){
+    unsafe { Port::new(port).write(value) }
+}
@@ -0,0 +1,63 @@
+// Per-file attributes the FileSystem has no room for.
+//
+// Like the directory table, the list of read-only files lives in a hidden file
+// the kernel rewrites whenever it changes: one zero-padded name per locked file.
+
+use crate::{MAX_FILENAME_BYTES, MAX_FILES_STORED};
+
+pub const ATTRIBUTES_FILE: &str = ".attrs";
+pub const ATTRIBUTES_BYTES: usize = MAX_FILES_STORED * MAX_FILENAME_BYTES;
+
+pub struct Attributes{
+    locked: [[u8; MAX_FILENAME_BYTES]; MAX_FILES_STORED],
+}
+
+impl Attributes{
+    pub fn new() -> Self{
+        Self{locked: [[0; MAX_FILENAME_BYTES]; MAX_FILES_STORED]}
+    }
+
+    pub fn is_locked(&self, name: [u8; MAX_FILENAME_BYTES]) -> bool{
+        name[0] != 0 && self.locked.contains(&name)
+    }
+
+    // False only when there is no room to lock another file.
+    pub fn set_locked(&mut self, name: [u8; MAX_FILENAME_BYTES], locked: bool) -> bool{
+        if !locked{
+            self.forget_file(name);
+            return true;
+        }
+        if self.is_locked(name){
+            return true;
+        }
+        match self.locked.iter_mut().find(|slot| slot[0] == 0){
+            Some(slot) => {
+                *slot = name;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn forget_file(&mut self, name: [u8; MAX_FILENAME_BYTES]){
+        for slot in self.locked.iter_mut(){
+            if *slot == name{
+                *slot = [0; MAX_FILENAME_BYTES];
+            }
+        }
+    }
+
+    pub fn to_bytes(&self, out: &mut [u8; ATTRIBUTES_BYTES]){
+        for (i, name) in self.locked.iter().enumerate(){
+            out[i*MAX_FILENAME_BYTES..(i+1)*MAX_FILENAME_BYTES].copy_from_slice(name);
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self{
+        let mut attributes = Self::new();
+        for (slot, name) in attributes.locked.iter_mut().zip(bytes.chunks_exact(MAX_FILENAME_BYTES)){
+            slot.copy_from_slice(name);
+        }
+        attributes
+    }
+}
@@ -0,0 +1,48 @@
+// The VGA text-mode hardware cursor, driven through the CRT controller.
+//
+// Each controller register is reached by writing its index to one port and then
+// reading or writing its value on the next. The card blinks the cursor itself, so
+// the kernel only has to move it when the insertion point changes.
+
+use pluggable_interrupt_os::vga_buffer::BUFFER_WIDTH;
+use x86_64::instructions::port::Port;
+
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+
+const CURSOR_START: u8 = 0x0A;
+const CURSOR_END: u8 = 0x0B;
+const LOCATION_HIGH: u8 = 0x0E;
+const LOCATION_LOW: u8 = 0x0F;
+
+const CURSOR_DISABLE: u8 = 0x20;
+// Scan lines of the 16-line character cell the cursor fills: an underline.
+const FIRST_SCAN_LINE: u8 = 14;
+const LAST_SCAN_LINE: u8 = 15;
+
+// Show the cursor under the screen cell at row and col.
+pub fn show(row: usize, col: usize){
+    let location = (row * BUFFER_WIDTH + col) as u16;
+    write_register(CURSOR_START, (read_register(CURSOR_START) & 0xC0) | FIRST_SCAN_LINE);
+    write_register(CURSOR_END, (read_register(CURSOR_END) & 0xE0) | LAST_SCAN_LINE);
+    write_register(LOCATION_HIGH, (location >> 8) as u8);
+    write_register(LOCATION_LOW, location as u8);
+}
+
+pub fn hide(){
+    write_register(CURSOR_START, CURSOR_DISABLE);
+}
+
+fn read_register(register: u8) -> u8{
+    unsafe {
+        Port::new(CRTC_INDEX).write(register);
+        Port::new(CRTC_DATA).read()
+    }
+}
+
+fn write_register(register: u8, value: u8){
+    unsafe {
+        Port::new(CRTC_INDEX).write(register);
+        Port::new(CRTC_DATA).write(value);
+    }
+}
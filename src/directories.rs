@@ -0,0 +1,173 @@
+// Nested directories layered over the flat FileSystem namespace.
+//
+// The FileSystem only knows a single list of file names, so the tree lives in a
+// table the kernel keeps in its own hidden file. Each file records the directory
+// it belongs to (the root when it has no entry), and each directory records its
+// name and parent. File names stay unique across the whole disk.
+
+use crate::{MAX_FILENAME_BYTES, MAX_FILES_STORED};
+
+pub const MAX_DIRECTORIES: usize = 16;
+pub const ROOT: usize = 0;
+pub const TABLE_FILE: &str = ".dirs";
+
+const DIRECTORY_RECORD: usize = MAX_FILENAME_BYTES + 2;
+const FILE_RECORD: usize = MAX_FILENAME_BYTES + 1;
+pub const TABLE_BYTES: usize = MAX_DIRECTORIES * DIRECTORY_RECORD + MAX_FILES_STORED * FILE_RECORD;
+
+#[derive(Clone, Copy)]
+struct Directory{
+    name: [u8; MAX_FILENAME_BYTES],
+    parent: usize,
+    used: bool,
+}
+
+pub struct DirectoryTree{
+    directories: [Directory; MAX_DIRECTORIES],
+    files: [([u8; MAX_FILENAME_BYTES], usize); MAX_FILES_STORED],
+}
+
+impl DirectoryTree{
+    pub fn new() -> Self{
+        let mut directories = [Directory{name: [0; MAX_FILENAME_BYTES], parent: ROOT, used: false}; MAX_DIRECTORIES];
+        directories[ROOT].used = true;
+        Self{directories, files: [([0; MAX_FILENAME_BYTES], ROOT); MAX_FILES_STORED]}
+    }
+
+    pub fn parent_of_file(&self, name: [u8; MAX_FILENAME_BYTES]) -> usize{
+        self.files.iter().find(|(file, _)| *file == name).map_or(ROOT, |(_, dir)| *dir)
+    }
+
+    // Files in the root need no entry, so moving one there just forgets it.
+    pub fn set_file_parent(&mut self, name: [u8; MAX_FILENAME_BYTES], dir: usize) -> bool{
+        self.forget_file(name);
+        if dir == ROOT{
+            return true;
+        }
+        match self.files.iter_mut().find(|(file, _)| file[0] == 0){
+            Some(slot) => {
+                *slot = (name, dir);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn forget_file(&mut self, name: [u8; MAX_FILENAME_BYTES]){
+        for slot in self.files.iter_mut(){
+            if slot.0 == name{
+                *slot = ([0; MAX_FILENAME_BYTES], ROOT);
+            }
+        }
+    }
+
+    pub fn parent(&self, dir: usize) -> usize{
+        self.directories[dir].parent
+    }
+
+    pub fn name(&self, dir: usize) -> [u8; MAX_FILENAME_BYTES]{
+        self.directories[dir].name
+    }
+
+    pub fn children(&self, dir: usize) -> impl Iterator<Item = usize> + '_{
+        (1..MAX_DIRECTORIES).filter(move |d| self.directories[*d].used && self.directories[*d].parent == dir)
+    }
+
+    pub fn find_child(&self, dir: usize, name: [u8; MAX_FILENAME_BYTES]) -> Option<usize>{
+        self.children(dir).find(|d| self.directories[*d].name == name)
+    }
+
+    pub fn create(&mut self, parent: usize, name: [u8; MAX_FILENAME_BYTES]) -> Option<usize>{
+        if self.find_child(parent, name).is_some(){
+            return None;
+        }
+        let dir = (1..MAX_DIRECTORIES).find(|d| !self.directories[*d].used)?;
+        self.directories[dir] = Directory{name, parent, used: true};
+        Some(dir)
+    }
+
+    pub fn is_empty(&self, dir: usize) -> bool{
+        self.children(dir).next().is_none() && !self.files.iter().any(|(file, parent)| file[0] != 0 && *parent == dir)
+    }
+
+    pub fn remove(&mut self, dir: usize){
+        if dir != ROOT{
+            self.directories[dir].used = false;
+        }
+    }
+
+    // Walk every component of a path but the last, starting at from (or the root
+    // for a leading '/'). Returns the directory reached and the final component.
+    pub fn resolve<'a>(&self, from: usize, path: &'a str) -> Option<(usize, &'a str)>{
+        let mut dir = if path.starts_with('/') { ROOT } else { from };
+        let path = path.trim_start_matches('/');
+        let (parents, leaf) = match path.rfind('/'){
+            Some(split) => (&path[..split], &path[split+1..]),
+            None => ("", path),
+        };
+        for component in parents.split('/'){
+            dir = match component{
+                "" | "." => dir,
+                ".." => self.parent(dir),
+                _ => self.find_child(dir, padded_name(component)?)?,
+            };
+        }
+        Some((dir, leaf))
+    }
+
+    pub fn to_bytes(&self, out: &mut [u8; TABLE_BYTES]){
+        let mut i = 0;
+        for directory in self.directories.iter(){
+            out[i] = directory.used as u8;
+            out[i+1] = directory.parent as u8;
+            out[i+2..i+DIRECTORY_RECORD].copy_from_slice(&directory.name);
+            i += DIRECTORY_RECORD;
+        }
+        for (name, parent) in self.files.iter(){
+            out[i] = *parent as u8;
+            out[i+1..i+FILE_RECORD].copy_from_slice(name);
+            i += FILE_RECORD;
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self{
+        let mut tree = Self::new();
+        if bytes.len() < TABLE_BYTES{
+            return tree;
+        }
+        let mut i = 0;
+        for directory in tree.directories.iter_mut(){
+            directory.used = bytes[i] != 0;
+            directory.parent = (bytes[i+1] as usize).min(MAX_DIRECTORIES-1);
+            directory.name.copy_from_slice(&bytes[i+2..i+DIRECTORY_RECORD]);
+            i += DIRECTORY_RECORD;
+        }
+        for (name, parent) in tree.files.iter_mut(){
+            *parent = (bytes[i] as usize).min(MAX_DIRECTORIES-1);
+            name.copy_from_slice(&bytes[i+1..i+FILE_RECORD]);
+            i += FILE_RECORD;
+        }
+        tree.directories[ROOT].used = true;
+        tree
+    }
+}
+
+// A name as stored in the directory, padded with zeros; None if it is too long.
+pub fn padded_name(name: &str) -> Option<[u8; MAX_FILENAME_BYTES]>{
+    if name.len() > MAX_FILENAME_BYTES{
+        return None;
+    }
+    let mut padded = [0; MAX_FILENAME_BYTES];
+    padded[..name.len()].copy_from_slice(name.as_bytes());
+    Some(padded)
+}
+
+// Names the prompt accepts: letters, digits, '_', '-' and '.', never leading with
+// a '.', which is kept for the kernel's own files.
+pub fn valid_filename(name: &str) -> bool{
+    !name.is_empty() && !name.starts_with('.') && name.chars().all(is_name_char)
+}
+
+pub fn is_name_char(c: char) -> bool{
+    c.is_ascii_alphanumeric() || "_-.".contains(c)
+}
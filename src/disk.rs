@@ -0,0 +1,50 @@
+// The block device under the FileSystem.
+//
+// Files live on the IDE disk when one answers; otherwise they go on a RamDisk
+// and last until reboot. The FileSystem reads and writes blocks through calls
+// that cannot fail, as a RamDisk's can't, so IDE errors are latched here for the
+// kernel to report after each file operation.
+
+use crate::ata::{AtaDisk, AtaError};
+use crate::{BLOCK_SIZE, NUM_BLOCKS};
+use crossbeam::atomic::AtomicCell;
+use file_system::{BlockDevice, RamDisk};
+
+static FIRST_ERROR: AtomicCell<Option<AtaError>> = AtomicCell::new(None);
+
+pub enum Disk{
+    Ata(AtaDisk<BLOCK_SIZE, NUM_BLOCKS>),
+    Ram(RamDisk<BLOCK_SIZE, NUM_BLOCKS>),
+}
+
+impl BlockDevice<BLOCK_SIZE> for Disk{
+    fn read(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]){
+        match self{
+            Disk::Ata(disk) => latch(disk.read(block, buffer)),
+            Disk::Ram(disk) => disk.read(block, buffer),
+        }
+    }
+
+    fn write(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]){
+        match self{
+            Disk::Ata(disk) => latch(disk.write(block, buffer)),
+            Disk::Ram(disk) => disk.write(block, buffer),
+        }
+    }
+}
+
+// The first error since the last call, if any.
+pub fn take_error() -> Option<AtaError>{
+    FIRST_ERROR.swap(None)
+}
+
+// Whether an error is waiting, leaving it for take_error to report.
+pub fn has_error() -> bool{
+    FIRST_ERROR.load().is_some()
+}
+
+pub fn latch(result: Result<(), AtaError>){
+    if let Err(e) = result{
+        let _ = FIRST_ERROR.compare_exchange(None, Some(e));
+    }
+}
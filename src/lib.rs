@@ -1,14 +1,32 @@
 #![no_std]
 #![feature(prelude_2024)]
 
-use file_system::FileSystem;
+extern crate alloc;
+
+pub mod allocator;
+mod ata;
+mod attributes;
+mod cursor;
+mod directories;
+mod disk;
+mod limits;
+mod modifiers;
+mod theme;
+
+use ata::{AtaDisk, Drive};
+use attributes::{Attributes, ATTRIBUTES_FILE, ATTRIBUTES_BYTES};
+use directories::{DirectoryTree, ROOT, TABLE_FILE, TABLE_BYTES, MAX_DIRECTORIES, padded_name, valid_filename, is_name_char};
+use file_system::{FileSystem, FileSystemResult, RamDisk};
+use disk::Disk;
+use limits::{MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES, RESERVED_FILES};
+use modifiers::Modifiers;
+use theme::{Theme, THEMES};
 use gc_heap::CopyingHeap;
 //use alloc::string::String;
 // use file_system_solution::{FileSystem, FileSystemResult};
 use pc_keyboard::{DecodedKey, KeyCode};
 use pluggable_interrupt_os::println;
 use pluggable_interrupt_os::vga_buffer::{BUFFER_WIDTH, BUFFER_HEIGHT, plot, ColorCode, Color, plot_str, is_drawable, plot_num};
-use ramdisk::RamDisk;
 use simple_interp::{Interpreter, InterpreterOutput, TickResult};
 //use simple_interp::{Interpreter, InterpreterOutput, i64_into_buffer};
 // use gc_heap::CopyingHeap;
@@ -21,37 +39,38 @@ use core::prelude::rust_2024::derive;
 use core::clone::Clone;
 use core::cmp::{PartialEq,Eq};
 use core::marker::Copy;
+use core::fmt::Write;
+use alloc::boxed::Box;
+use alloc::vec;
 
 const FIRST_BORDER_ROW: usize = 1;
 const LAST_BORDER_ROW: usize = BUFFER_HEIGHT - 1;
 const TASK_MANAGER_WIDTH: usize = 10;
 const TASK_MANAGER_BYTES: usize = BUFFER_HEIGHT * TASK_MANAGER_WIDTH;
 const WINDOWS_WIDTH: usize = BUFFER_WIDTH - TASK_MANAGER_WIDTH;
-const WINDOW_WIDTH: usize = (WINDOWS_WIDTH) / 2; //had to change to work with struct
-const WINDOW_HEIGHT: usize = ((LAST_BORDER_ROW - FIRST_BORDER_ROW) / 2) + 1; //had to change to work with struct
-const MID_WIDTH: usize = WINDOWS_WIDTH / 2;
-const MID_HEIGHT: usize = BUFFER_HEIGHT / 2;
+const LINE_WIDTH: usize = WINDOWS_WIDTH / 2; // the interpreter's input and output line length
 const NUM_WINDOWS: usize = 4;
+// The area layouts divide: every row between the prompt and the status bar.
+const MAX_WINDOW_WIDTH: usize = WINDOWS_WIDTH;
+const MAX_WINDOW_HEIGHT: usize = LAST_BORDER_ROW - FIRST_BORDER_ROW;
+const MAX_CONTENT_CELLS: usize = (MAX_WINDOW_WIDTH-2) * (MAX_WINDOW_HEIGHT-2);
+const MAX_GRID_NAMES: usize = (MAX_WINDOW_WIDTH-5) / MAX_FILENAME_BYTES * (MAX_WINDOW_HEIGHT-2);
 
 const FILENAME_PROMPT: &str = "F5 - Filename: ";
+const RENAME_PROMPT: &str = "Rename to: ";
+const COPY_PROMPT: &str = "Copy to: ";
+const MKDIR_PROMPT: &str = "Mkdir: ";
+const OPEN_PROMPT: &str = "Open: ";
+const RUN_PROMPT: &str = "Run: ";
 const F6: &str = "(F6)";
+const INDENT_WIDTH: usize = 4;
+const GUTTER_WIDTH: usize = 4;
+// The reserved words of the simple_interp grammar; keep in sync with its
+// tokenizer when the language gains or loses keywords.
+const KEYWORDS: [&str; 7] = ["while", "if", "else", "print", "input", "true", "false"];
 
-const MAX_OPEN: usize = 16;
-const BLOCK_SIZE: usize = 256;
-const NUM_BLOCKS: usize = 255;
-const MAX_FILE_BLOCKS: usize = 8;  //Works as 8, but not as 64???
-const MAX_FILE_BYTES: usize = MAX_FILE_BLOCKS * BLOCK_SIZE;
-const MAX_FILES_STORED: usize = 30;
-const MAX_FILENAME_BYTES: usize = 10;
-/*
-const MAX_OPEN: usize = 16;
-const BLOCK_SIZE: usize = 256;
-const NUM_BLOCKS: usize = 255;
-const MAX_FILE_BLOCKS: usize = 64;
-const MAX_FILE_BYTES: usize = MAX_FILE_BLOCKS * BLOCK_SIZE;
-const MAX_FILES_STORED: usize = 30;
-const MAX_FILENAME_BYTES: usize = 10; 
-*/
+// The hidden tables are the files RESERVED_FILES keeps room for.
+const _: () = assert!(RESERVED_FILES == [TABLE_FILE, ATTRIBUTES_FILE].len());
 
 const MAX_TOKENS: usize = 500;
 const MAX_LITERAL_CHARS: usize = 30;
@@ -60,46 +79,155 @@ const MAX_LOCAL_VARS: usize = 20;
 const HEAP_SIZE: usize = 1024;
 const MAX_HEAP_BLOCKS: usize = HEAP_SIZE;
 
-const MAX_USER_INPUT_BYTES: usize = MAX_FILENAME_BYTES + FILENAME_PROMPT.len();
+// A path through every level of directory to a file: a name and a '/' per level.
+const MAX_PATH_BYTES: usize = MAX_DIRECTORIES * (MAX_FILENAME_BYTES + 1) + MAX_FILENAME_BYTES;
+const PROMPT_WIDTH: usize = BUFFER_WIDTH - TASK_MANAGER_WIDTH;
+const MAX_USER_INPUT_BYTES: usize = MAX_PATH_BYTES + FILENAME_PROMPT.len();
+const MAX_ENTRIES: usize = MAX_FILES_STORED + MAX_DIRECTORIES + 1;
+const TICKS_PER_SECOND: usize = 18; // the timer's default rate is about 18.2 Hz
+const RATE_ROW: usize = 9;
 
 // Data type for a file system object:
-// FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES>
+// FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES, Disk>
+type Files = FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES, Disk>;
 
 // Data type for an interpreter object:
-// Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>
+// Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder{
+    Created,
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortOrder{
+    pub fn next(self) -> Self{
+        match self{
+            SortOrder::Created => SortOrder::Name,
+            SortOrder::Name => SortOrder::Size,
+            SortOrder::Size => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Created,
+        }
+    }
+    pub fn label(self) -> &'static str{
+        match self{
+            SortOrder::Created => "",
+            SortOrder::Name => "name",
+            SortOrder::Size => "size",
+            SortOrder::Modified => "recent",
+        }
+    }
+}
+
+// How the area below the prompt is split between windows.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Layout{
+    Full,
+    SideBySide,
+    Stacked,
+    Grid,
+}
+
+impl Layout{
+    pub fn next(self) -> Self{
+        match self{
+            Layout::Full => Layout::SideBySide,
+            Layout::SideBySide => Layout::Stacked,
+            Layout::Stacked => Layout::Grid,
+            Layout::Grid => Layout::Full,
+        }
+    }
+    pub fn label(self) -> &'static str{
+        match self{
+            Layout::Full => "one window",
+            Layout::SideBySide => "side by side",
+            Layout::Stacked => "stacked",
+            Layout::Grid => "2x2",
+        }
+    }
+    // Columns and rows of windows.
+    fn shape(self) -> (usize, usize){
+        match self{
+            Layout::Full => (1, 1),
+            Layout::SideBySide => (2, 1),
+            Layout::Stacked => (1, 2),
+            Layout::Grid => (2, 2),
+        }
+    }
+    pub fn slots(self) -> usize{
+        let (columns, rows) = self.shape();
+        columns * rows
+    }
+    // Top row, left column, width and height of a slot, counted left to right then
+    // top to bottom. Neighbouring windows share the border line between them.
+    pub fn rect(self, slot: usize) -> (usize, usize, usize, usize){
+        let (columns, rows) = self.shape();
+        let edge = |k: usize, n: usize, total: usize| k * (total-1) / n;
+        let (col, row) = (slot % columns, slot / columns);
+        let (left, right) = (edge(col, columns, MAX_WINDOW_WIDTH), edge(col+1, columns, MAX_WINDOW_WIDTH));
+        let (top, bottom) = (edge(row, rows, MAX_WINDOW_HEIGHT), edge(row+1, rows, MAX_WINDOW_HEIGHT));
+        (FIRST_BORDER_ROW + top, left, right - left + 1, bottom - top + 1)
+    }
+}
 
 pub struct Window{
-    vga:[[char; WINDOW_WIDTH];WINDOW_HEIGHT],
+    vga: Box<[[char; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]>,
     in_use: bool,
     win_num: char,
-    contents:[[char;WINDOW_WIDTH-2];WINDOW_HEIGHT-2],
-    foreground:[[Color; WINDOW_WIDTH];WINDOW_HEIGHT],
-    background:[[Color; WINDOW_WIDTH];WINDOW_HEIGHT],
+    contents: Box<[[char; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2]>,
+    foreground: Box<[[Color; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]>,
+    background: Box<[[Color; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]>,
     current_highlighted: usize,
     is_being_edited: bool,
     current_contents_index: usize,
     current_file: [u8; MAX_FILENAME_BYTES],
-    all_contents_u8: [u8; MAX_FILE_BYTES],
+    all_contents_u8: Box<[u8; MAX_FILE_BYTES]>,
     current_u8_index: usize,
-    
+    cursor: usize,
+    file_len: usize,
+    scroll_row: usize,
+    brace_cell: Option<(usize, usize)>,
+    match_cell: Option<(usize, usize)>,
+    editor_open: bool,
+    read_only: bool,
+    viewing: usize,
+    show_gutter: bool,
+    details: bool,
+    list_offset: usize,
+    file_count: usize,
+    filter: [u8; MAX_FILENAME_BYTES],
+    filter_len: usize,
+    sort: SortOrder,
+    // The entries listed last, in grid order, so keys act on what is shown.
+    view: [Entry; MAX_ENTRIES],
+    current_dir: usize,
+    dir_name: [u8; MAX_FILENAME_BYTES],
+    locked: [bool; MAX_GRID_NAMES],
+    selection_anchor: Option<usize>,
+    shift_selection: bool,
+    line_selection: Option<(usize, usize)>,
+    selected: Box<[[bool; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2]>,
+    width: usize,
+    height: usize,
+    theme: &'static Theme,
 }
 impl Clone for Window{
     fn clone(&self) -> Self {
-        Self { vga: self.vga.clone(), in_use: self.in_use.clone(), win_num: self.win_num.clone(), contents: self.contents.clone(), foreground: self.foreground.clone(), background: self.background.clone(), current_highlighted: self.current_highlighted.clone(), is_being_edited: self.is_being_edited.clone(), current_contents_index: self.current_contents_index.clone(), current_file: self.current_file.clone(), all_contents_u8: self.all_contents_u8.clone(), current_u8_index: self.current_u8_index.clone() }
+        Self { vga: self.vga.clone(), in_use: self.in_use.clone(), win_num: self.win_num.clone(), contents: self.contents.clone(), foreground: self.foreground.clone(), background: self.background.clone(), current_highlighted: self.current_highlighted.clone(), is_being_edited: self.is_being_edited.clone(), current_contents_index: self.current_contents_index.clone(), current_file: self.current_file.clone(), all_contents_u8: self.all_contents_u8.clone(), current_u8_index: self.current_u8_index.clone(), cursor: self.cursor.clone(), file_len: self.file_len.clone(), scroll_row: self.scroll_row.clone(), brace_cell: self.brace_cell.clone(), match_cell: self.match_cell.clone(), editor_open: self.editor_open.clone(), read_only: self.read_only.clone(), viewing: self.viewing.clone(), show_gutter: self.show_gutter.clone(), details: self.details.clone(), list_offset: self.list_offset.clone(), file_count: self.file_count.clone(), filter: self.filter.clone(), filter_len: self.filter_len.clone(), sort: self.sort.clone(), view: self.view, current_dir: self.current_dir.clone(), dir_name: self.dir_name.clone(), locked: self.locked.clone(), selection_anchor: self.selection_anchor.clone(), shift_selection: self.shift_selection.clone(), line_selection: self.line_selection.clone(), selected: self.selected.clone(), width: self.width.clone(), height: self.height.clone(), theme: self.theme }
     }
-}
-impl Copy for Window{
-
 }
 impl Window {
     pub fn new(win_num: char) -> Self{
-        let mut vga=[['\0'; WINDOW_WIDTH];WINDOW_HEIGHT];
-        let mut contents = [['\0';WINDOW_WIDTH-2]; WINDOW_HEIGHT-2];
-        let mut foreground=[[Color::White; WINDOW_WIDTH];WINDOW_HEIGHT];
-        let mut background=[[Color::Black; WINDOW_WIDTH];WINDOW_HEIGHT];
+        let mut vga = Box::new([['\0'; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]);
+        let mut contents = Box::new([['\0'; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2]);
+        let theme = &THEMES[0];
+        let mut foreground = Box::new([[theme.text.0; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]);
+        let mut background = Box::new([[theme.text.1; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT]);
         let mut current_file = ['*' as u8;MAX_FILENAME_BYTES];
-        let mut all_contents_u8 = ['\0' as u8; MAX_FILE_BYTES];
-        Self{vga, in_use: false, win_num, contents, foreground, background, current_highlighted: 0, is_being_edited: false, current_file, current_contents_index: 0, all_contents_u8, current_u8_index: 0}
+        let mut all_contents_u8 = file_buffer();
+        Self{vga, in_use: false, win_num, contents, foreground, background, current_highlighted: 0, is_being_edited: false, current_file, current_contents_index: 0, all_contents_u8, current_u8_index: 0, cursor: 0, file_len: 0, scroll_row: 0, brace_cell: None, match_cell: None, editor_open: false, read_only: false, viewing: 0, show_gutter: false, details: false, list_offset: 0, file_count: 0, filter: [0; MAX_FILENAME_BYTES], filter_len: 0, sort: SortOrder::Created, view: [Entry::Parent; MAX_ENTRIES], current_dir: ROOT, dir_name: [0; MAX_FILENAME_BYTES], locked: [false; MAX_GRID_NAMES], selection_anchor: None, shift_selection: false, line_selection: None, selected: Box::new([[false; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2]), width: MAX_WINDOW_WIDTH, height: MAX_WINDOW_HEIGHT, theme}
     }
 
 
@@ -107,10 +235,14 @@ impl Window {
     pub fn set_in_use(&mut self,val: bool){
         self.in_use = val;
     }
-    pub fn update_contents(&mut self, c: [[char;WINDOW_WIDTH-2];WINDOW_HEIGHT-2]){
-        self.contents = c;
-        for i in 1..WINDOW_HEIGHT-1{
-            for j in 1..WINDOW_WIDTH-1{
+    // Columns and rows inside the border.
+    pub fn content_size(&self) -> (usize, usize){
+        (self.width-2, self.height-2)
+    }
+    pub fn update_contents(&mut self, c: [[char; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2]){
+        *self.contents = c;
+        for i in 1..self.height-1{
+            for j in 1..self.width-1{
                 self.vga[i][j] = self.contents[i-1][j-1];
             }
         }
@@ -138,13 +270,14 @@ impl Window {
         self.current_u8_index = type_index;
     }
     pub fn find_contents_index(&mut self){
-        let mut type_index = (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2);
+        let (cols, rows) = self.content_size();
+        let mut type_index = cols*rows;
         let mut found = false;
-        for i in self.contents.iter().rev(){
+        for i in self.contents[..rows].iter().rev(){
             if found{
                 break;
             }
-            for j in i.iter().rev(){
+            for j in i[..cols].iter().rev(){
                 if *j !='\0'{
                     found = true;
                     break;
@@ -172,8 +305,9 @@ impl Window {
         }
     }
     pub fn edit_press_enter(&mut self){
-        let row = (self.current_contents_index / (WINDOW_WIDTH-2)) + 1;
-        if row < WINDOW_HEIGHT-2 {
+        let (cols, rows) = self.content_size();
+        let row = (self.current_contents_index / cols) + 1;
+        if row < rows {
             let mut count = 0;
             //self.type_char(' ');
             // for i in (self.current_contents_index+1)..(row*(WINDOW_WIDTH-2)+2){
@@ -184,7 +318,7 @@ impl Window {
             //     count +=1;
             // }
 
-            self.current_contents_index =(row*(WINDOW_WIDTH-2)+1);
+            self.current_contents_index =(row*cols+1);
             self.type_char('\n');
             // self.current_u8_index-=2;
             self.current_contents_index-=2;
@@ -195,23 +329,22 @@ impl Window {
         
     }
     pub fn type_char(&mut self, c: char){  //backspace error dont increment
-        if self.current_contents_index != (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2){
-            if c=='\n'{
-                self.contents[self.current_contents_index/(WINDOW_WIDTH-2)][self.current_contents_index%(WINDOW_WIDTH-2)] = ' ';
-            }
-            else{
-                self.contents[self.current_contents_index/(WINDOW_WIDTH-2)][self.current_contents_index%(WINDOW_WIDTH-2)] = c;
-            }
+        let (cols, rows) = self.content_size();
+        if self.current_contents_index != cols*rows{
+            let (row, col) = (self.current_contents_index/cols, self.current_contents_index%cols);
+            let cell = if c=='\n' { ' ' } else { c };
+            self.contents[row][col] = cell;
+            // Only this cell changed, so write it through rather than copying the whole grid.
+            self.vga[row+1][col+1] = cell;
             //self.contents[self.current_contents_index/(WINDOW_WIDTH-2)][self.current_contents_index%(WINDOW_WIDTH-2)] = c;
             self.all_contents_u8[self.current_u8_index] = c as u8;
-            self.update_contents(self.contents);
             if (c!='\0'){
                 self.current_contents_index+=1;
                 self.current_u8_index +=1;
             }
             else{
                 if self.current_contents_index > 1{
-                    if (self.contents[(self.current_contents_index-1)/(WINDOW_WIDTH-2)][(self.current_contents_index-1)%(WINDOW_WIDTH-2)] == '\0' || (self.contents[(self.current_contents_index-1)/(WINDOW_WIDTH-2)][(self.current_contents_index-1)%(WINDOW_WIDTH-2)] == ' ' && self.contents[(self.current_contents_index-2)/(WINDOW_WIDTH-2)][(self.current_contents_index-2)%(WINDOW_WIDTH-2)] == '\0')) && (self.current_contents_index)%(WINDOW_WIDTH-2) !=0{
+                    if (self.contents[(self.current_contents_index-1)/cols][(self.current_contents_index-1)%cols] == '\0' || (self.contents[(self.current_contents_index-1)/cols][(self.current_contents_index-1)%cols] == ' ' && self.contents[(self.current_contents_index-2)/cols][(self.current_contents_index-2)%cols] == '\0')) && (self.current_contents_index)%cols !=0{
                         self.current_contents_index-=1;
                         self.type_char('\0');
                     }
@@ -220,17 +353,495 @@ impl Window {
             }
         }
     }
+
+    // Editor buffer: all_contents_u8[..file_len] is the file, cursor is the insertion point.
+    pub fn load_for_editing(&mut self, buffer: &[u8]){
+        self.all_contents_u8.fill(0);
+        self.all_contents_u8[..buffer.len()].copy_from_slice(buffer);
+        self.find_u8_index();
+        self.file_len = self.current_u8_index;
+        self.cursor = self.file_len;
+        self.scroll_row = 0;
+        self.editor_open = true;
+        self.read_only = false;
+        self.viewing = 0;
+        self.render_editor();
+    }
+    pub fn edit_insert(&mut self, c: u8){
+        if self.file_len < MAX_FILE_BYTES{
+            self.all_contents_u8.copy_within(self.cursor..self.file_len, self.cursor+1);
+            self.all_contents_u8[self.cursor] = c;
+            self.file_len +=1;
+            self.cursor +=1;
+        }
+    }
+    pub fn edit_backspace(&mut self){
+        if self.cursor > 0{
+            self.all_contents_u8.copy_within(self.cursor..self.file_len, self.cursor-1);
+            self.file_len -=1;
+            self.cursor -=1;
+            self.all_contents_u8[self.file_len] = 0;
+        }
+    }
+    pub fn edit_move_left(&mut self){
+        if self.cursor > 0{
+            self.cursor -=1;
+        }
+    }
+    pub fn edit_move_right(&mut self){
+        if self.cursor < self.file_len{
+            self.cursor +=1;
+        }
+    }
+    fn line_start(&self, index: usize) -> usize{
+        let mut start = index;
+        while start > 0 && self.all_contents_u8[start-1] != b'\n'{
+            start -=1;
+        }
+        start
+    }
+    pub fn edit_newline(&mut self){
+        let start = self.line_start(self.cursor);
+        let mut indent = 0;
+        while start + indent < self.cursor && self.all_contents_u8[start+indent] == b' '{
+            indent +=1;
+        }
+        let mut before = self.cursor;
+        while before > start && self.all_contents_u8[before-1] == b' '{
+            before -=1;
+        }
+        if before > start && self.all_contents_u8[before-1] == b'{'{
+            indent += INDENT_WIDTH;
+        }
+        self.edit_insert(b'\n');
+        for _ in 0..indent{
+            self.edit_insert(b' ');
+        }
+    }
+    pub fn edit_tab(&mut self){
+        let column = self.cursor - self.line_start(self.cursor);
+        for _ in 0..(INDENT_WIDTH - column % INDENT_WIDTH){
+            self.edit_insert(b' ');
+        }
+    }
+    pub fn edit_close_brace(&mut self){
+        let start = self.line_start(self.cursor);
+        if self.all_contents_u8[start..self.cursor].iter().all(|b| *b == b' '){
+            for _ in 0..INDENT_WIDTH.min(self.cursor - start){
+                self.edit_backspace();
+            }
+        }
+        self.edit_insert(b'}');
+    }
+    fn matching_brace(&self, index: usize) -> Option<usize>{
+        let mut depth = 0;
+        if self.all_contents_u8[index] == b'{'{
+            for i in index..self.file_len{
+                if self.all_contents_u8[i] == b'{'{
+                    depth +=1;
+                }
+                else if self.all_contents_u8[i] == b'}'{
+                    depth -=1;
+                    if depth == 0{
+                        return Some(i);
+                    }
+                }
+            }
+        }
+        else if self.all_contents_u8[index] == b'}'{
+            for i in (0..=index).rev(){
+                if self.all_contents_u8[i] == b'}'{
+                    depth +=1;
+                }
+                else if self.all_contents_u8[i] == b'{'{
+                    depth -=1;
+                    if depth == 0{
+                        return Some(i);
+                    }
+                }
+            }
+        }
+        None
+    }
+    fn line_end(&self, index: usize) -> usize{
+        let mut end = index;
+        while end < self.file_len && self.all_contents_u8[end] != b'\n'{
+            end +=1;
+        }
+        end
+    }
+    pub fn edit_move_up(&mut self){
+        let start = self.line_start(self.cursor);
+        if start > 0{
+            let column = self.cursor - start;
+            let previous = self.line_start(start-1);
+            self.cursor = previous + column.min(start-1-previous);
+        }
+    }
+    pub fn edit_move_down(&mut self){
+        let column = self.cursor - self.line_start(self.cursor);
+        let end = self.line_end(self.cursor);
+        if end < self.file_len{
+            let next_end = self.line_end(end+1);
+            self.cursor = end+1 + column.min(next_end-(end+1));
+        }
+    }
+    // Logical line and column of the cursor, both starting at 1.
+    pub fn cursor_line_col(&self) -> (usize, usize){
+        let line = self.all_contents_u8[..self.cursor].iter().filter(|b| **b == b'\n').count() + 1;
+        (line, self.cursor - self.line_start(self.cursor) + 1)
+    }
+    // Wide enough for the last line's number and a space, never under GUTTER_WIDTH.
+    fn gutter_width(&self) -> usize{
+        if self.show_gutter{
+            let mut lines = self.all_contents_u8[..self.file_len].iter().filter(|b| **b == b'\n').count() + 1;
+            let mut digits = 1;
+            while lines >= 10{
+                lines /= 10;
+                digits +=1;
+            }
+            GUTTER_WIDTH.max(digits+1)
+        }
+        else{
+            0
+        }
+    }
+    fn text_width(&self) -> usize{
+        self.width-2 - self.gutter_width()
+    }
+    // Screen row and column of a buffer index, counting soft-wrapped rows from the top of the file.
+    fn editor_position(&self, index: usize) -> (usize, usize){
+        let width = self.text_width();
+        let mut row = 0;
+        let mut col = 0;
+        for i in 0..index{
+            if self.all_contents_u8[i] == b'\n'{
+                row +=1;
+                col = 0;
+            }
+            else{
+                if col == width{
+                    row +=1;
+                    col = 0;
+                }
+                col +=1;
+            }
+        }
+        if col == width && (index == self.file_len || self.all_contents_u8[index] != b'\n'){
+            row +=1;
+            col = 0;
+        }
+        (row, col)
+    }
+    fn visible_cell(&self, index: usize) -> Option<(usize, usize)>{
+        let (row, col) = self.editor_position(index);
+        if row >= self.scroll_row && row < self.scroll_row + self.height-2{
+            Some((row - self.scroll_row, col + self.gutter_width()))
+        }
+        else{
+            None
+        }
+    }
+    fn gutter_number(row: &mut [char], line: usize, gutter: usize){
+        let mut n = line;
+        for i in (0..gutter-1).rev(){
+            row[i] = char::from_digit((n % 10) as u32, 10).unwrap();
+            n /= 10;
+            if n == 0{
+                break;
+            }
+        }
+    }
+    pub fn render_editor(&mut self){
+        let width = self.text_width();
+        let offset = self.gutter_width();
+        let (cursor_row, _) = self.editor_position(self.cursor);
+        if cursor_row < self.scroll_row{
+            self.scroll_row = cursor_row;
+        }
+        else if cursor_row >= self.scroll_row + self.height-2{
+            self.scroll_row = cursor_row + 3 - self.height;
+        }
+        let visible = |row: usize| row >= self.scroll_row && row < self.scroll_row + self.height-2;
+        let (select_start, select_end) = self.selection_range().unwrap_or((0, 0));
+        let mut selected = [[false; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
+        let mut c = [['\0'; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
+        let mut row = 0;
+        let mut col = 0;
+        let mut line = 1;
+        if self.show_gutter && visible(row){
+            Self::gutter_number(&mut c[row - self.scroll_row], line, offset);
+        }
+        for i in 0..self.file_len{
+            let b = self.all_contents_u8[i];
+            if b == b'\n'{
+                row +=1;
+                col = 0;
+                line +=1;
+                if self.show_gutter && visible(row){
+                    Self::gutter_number(&mut c[row - self.scroll_row], line, offset);
+                }
+                continue;
+            }
+            if col == width{
+                row +=1;
+                col = 0;
+            }
+            if visible(row){
+                c[row - self.scroll_row][col + offset] = b as char;
+                selected[row - self.scroll_row][col + offset] = i >= select_start && i < select_end;
+            }
+            col +=1;
+        }
+        *self.selected = selected;
+        self.brace_cell = None;
+        self.match_cell = None;
+        let is_brace = |b: u8| b == b'{' || b == b'}';
+        let mut brace = None;
+        if self.cursor < self.file_len && is_brace(self.all_contents_u8[self.cursor]){
+            brace = Some(self.cursor);
+        }
+        else if self.cursor > 0 && is_brace(self.all_contents_u8[self.cursor-1]){
+            brace = Some(self.cursor-1);
+        }
+        if let Some(index) = brace{
+            self.brace_cell = self.visible_cell(index);
+            self.match_cell = self.matching_brace(index).and_then(|m| self.visible_cell(m));
+        }
+        self.update_contents(c);
+    }
+    pub fn selection_range(&self) -> Option<(usize, usize)>{
+        match self.selection_anchor{
+            Some(anchor) if anchor != self.cursor => Some((anchor.min(self.cursor), anchor.max(self.cursor))),
+            _ => None,
+        }
+    }
+    pub fn delete_range(&mut self, start: usize, end: usize){
+        let removed = end - start;
+        self.all_contents_u8.copy_within(end..self.file_len, start);
+        for i in self.file_len-removed..self.file_len{
+            self.all_contents_u8[i] = 0;
+        }
+        self.file_len -= removed;
+        self.cursor = start;
+        self.selection_anchor = None;
+    }
+    // Editors select from an anchor to the cursor; other windows select whole rows.
+    pub fn toggle_selection(&mut self){
+        if self.editor_open{
+            self.selection_anchor = match self.selection_anchor{
+                Some(_) => None,
+                None => Some(self.cursor),
+            };
+            self.shift_selection = false;
+            self.render_editor();
+        }
+        else{
+            self.line_selection = match self.line_selection{
+                Some(_) => None,
+                None => {
+                    let (cols, rows) = self.content_size();
+                    let row = (self.current_contents_index / cols).min(rows-1);
+                    Some((row, row))
+                }
+            };
+            self.mark_line_selection();
+        }
+    }
+    // Shift+arrow anchors a selection where the cursor was before it moves; a plain
+    // arrow drops a selection shift started, while one marked with F8 keeps following.
+    pub fn before_cursor_move(&mut self, shift: bool){
+        if shift{
+            if self.selection_anchor.is_none(){
+                self.selection_anchor = Some(self.cursor);
+                self.shift_selection = true;
+            }
+        }
+        else if self.shift_selection{
+            self.selection_anchor = None;
+            self.shift_selection = false;
+        }
+    }
+    pub fn move_line_selection(&mut self, down: bool){
+        if let Some((anchor, row)) = self.line_selection{
+            let row = if down { (row+1).min(self.height-3) } else { row.saturating_sub(1) };
+            self.line_selection = Some((anchor, row));
+            self.mark_line_selection();
+        }
+    }
+    fn mark_line_selection(&mut self){
+        *self.selected = [[false; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
+        if let Some((anchor, row)) = self.line_selection{
+            for r in anchor.min(row)..=anchor.max(row){
+                self.selected[r] = [true; MAX_WINDOW_WIDTH-2];
+            }
+        }
+    }
+    pub fn clear_selection(&mut self){
+        self.selection_anchor = None;
+        self.line_selection = None;
+        *self.selected = [[false; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
+    }
+    // Copy the selected rows into out with trailing blanks trimmed, one '\n' per row.
+    pub fn copy_lines(&self, out: &mut [u8]) -> usize{
+        let mut len = 0;
+        if let Some((anchor, row)) = self.line_selection{
+            for r in anchor.min(row)..=anchor.max(row){
+                let mut end = self.width-2;
+                while end > 0 && (self.contents[r][end-1] == '\0' || self.contents[r][end-1] == ' '){
+                    end -=1;
+                }
+                for c in &self.contents[r][..end]{
+                    if len < out.len(){
+                        out[len] = if *c == '\0' { b' ' } else { *c as u8 };
+                        len +=1;
+                    }
+                }
+                if len < out.len(){
+                    out[len] = b'\n';
+                    len +=1;
+                }
+            }
+        }
+        len
+    }
+    pub fn close_editor(&mut self){
+        self.is_being_edited = false;
+        self.editor_open = false;
+        self.read_only = false;
+        self.viewing = 0;
+        self.clear_selection();
+        self.brace_cell = None;
+        self.match_cell = None;
+    }
+
+    // Give the window a new size. Editors re-wrap their buffer and program output is
+    // re-flowed into the new width; the kernel redraws browser listings itself.
+    pub fn resize(&mut self, width: usize, height: usize){
+        let (cols, rows) = self.content_size();
+        let at_line_start = self.current_contents_index % cols == 0;
+        // Output text in reading order: a row that stops short of the border ends a line.
+        let mut text = vec!['\0'; MAX_CONTENT_CELLS + MAX_WINDOW_HEIGHT];
+        let mut len = 0;
+        for row in &self.contents[..rows]{
+            let end = row[..cols].iter().rposition(|c| *c != '\0').map_or(0, |end| end+1);
+            for c in &row[..end]{
+                text[len] = *c;
+                len +=1;
+            }
+            if end < cols{
+                text[len] = '\n';
+                len +=1;
+            }
+        }
+        while len > 0 && text[len-1] == '\n'{
+            len -=1;
+        }
+        self.width = width;
+        self.height = height;
+        self.clear_selection();
+        if self.editor_open{
+            self.render_editor();
+            return;
+        }
+        let (cols, rows) = self.content_size();
+        let mut c = [['\0'; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
+        let (mut row, mut col) = (0, 0);
+        for ch in &text[..len]{
+            if *ch == '\n' || col == cols{
+                row +=1;
+                col = 0;
+            }
+            if row == rows{
+                break;
+            }
+            if *ch != '\n'{
+                c[row][col] = *ch;
+                col +=1;
+            }
+        }
+        self.update_contents(c);
+        self.find_contents_index();
+        if at_line_start && len > 0 && self.current_contents_index % cols != 0{
+            self.current_contents_index = (self.current_contents_index / cols + 1) * cols;
+            self.current_contents_index = self.current_contents_index.min(cols*rows);
+        }
+    }
+
     pub fn reset_colors(&mut self){
-        self.foreground = [[Color::White; WINDOW_WIDTH];WINDOW_HEIGHT];
-        self.background = [[Color::Black; WINDOW_WIDTH];WINDOW_HEIGHT];
+        *self.foreground = [[self.theme.text.0; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT];
+        *self.background = [[self.theme.text.1; MAX_WINDOW_WIDTH]; MAX_WINDOW_HEIGHT];
     }
     pub fn update_colors(&mut self){
         self.reset_colors();
-        let row = ((self.current_highlighted) /3) + 1;
-        let col = self.current_highlighted % 3;
+        if self.file_count == 0{
+            return;
+        }
+        let columns = self.grid_columns();
+        if !self.details{
+            for (k, locked) in self.locked.iter().take(self.page_rows() * columns).enumerate(){
+                if *locked{
+                    for i in 0..MAX_FILENAME_BYTES{
+                        self.foreground[(k/columns)+1][((k%columns)*10)+1 + i] = self.theme.locked;
+                    }
+                }
+            }
+        }
+        if self.current_highlighted < self.list_offset{
+            return;
+        }
+        let shown = self.current_highlighted - self.list_offset;
+        if self.details{
+            let row = shown + 1;
+            if row < self.height-2{
+                for i in 1..self.width-1{
+                    self.foreground[row][i] = self.theme.highlight.0;
+                    self.background[row][i] = self.theme.highlight.1;
+                }
+            }
+            return;
+        }
+        let row = (shown /columns) + 1;
+        let col = shown % columns;
+        if row > self.height-2{
+            return;
+        }
         for i in 0..MAX_FILENAME_BYTES{
-            self.foreground[row][((col*10)+1) + i] = Color::Black;
-            self.background[row][((col*10)+1) + i] = Color::White;
+            self.foreground[row][((col*10)+1) + i] = self.theme.highlight.0;
+            self.background[row][((col*10)+1) + i] = self.theme.highlight.1;
+        }
+    }
+    // Rows of files on one browser page; the details view keeps its last row for the footer.
+    pub fn page_rows(&self) -> usize{
+        if self.details{
+            self.height-3
+        }
+        else{
+            self.height-2
+        }
+    }
+    // Keep the highlighted file inside the page that starts at list_offset.
+    pub fn scroll_to_highlight(&mut self){
+        let columns = self.grid_columns();
+        let row = self.current_highlighted / columns;
+        let first = self.list_offset / columns;
+        if row < first{
+            self.list_offset = row * columns;
+        }
+        else if row >= first + self.page_rows(){
+            self.list_offset = (row + 1 - self.page_rows()) * columns;
+        }
+        else{
+            self.list_offset = first * columns;
+        }
+    }
+    // Files per row of the browser: as many names as fit the grid, one in the details view.
+    pub fn grid_columns(&self) -> usize{
+        if self.details{
+            1
+        }
+        else{
+            (self.width-5) / MAX_FILENAME_BYTES
         }
     }
     pub fn update_borders(&mut self){
@@ -238,15 +849,15 @@ impl Window {
         if self.in_use{ 
             border_char = '*';
         } 
-        for i in 0..WINDOW_HEIGHT{ 
-            for j in 0..WINDOW_WIDTH{
-                if i==0 || i == WINDOW_HEIGHT-1 || j == 0 || j == WINDOW_WIDTH-1{
+        for i in 0..self.height{ 
+            for j in 0..self.width{
+                if i==0 || i == self.height-1 || j == 0 || j == self.width-1{
                     self.vga[i][j] = border_char;
                     if (i==0){
-                        if (j==MID_WIDTH/2){
+                        if (j==self.width/2){
                             self.vga[i][j] = 'F'
                         }
-                        else if (j==(MID_WIDTH/2)+1){
+                        else if (j==(self.width/2)+1){
                             self.vga[i][j] = self.win_num;
                         }
                     }
@@ -256,22 +867,145 @@ impl Window {
         if self.is_being_edited{
             self.start_editing();
         }
+        else{
+            self.vga[0][2] = '/';
+            for (i, b) in self.dir_name.iter().take_while(|b| **b != 0).enumerate(){
+                self.vga[0][3+i] = *b as char;
+            }
+            let mut col = 2;
+            for c in self.sort.label().chars(){
+                self.vga[self.height-1][col] = c;
+                col +=1;
+            }
+            if self.filter_len > 0{
+                col +=1;
+                self.vga[self.height-1][col] = '/';
+                for b in &self.filter[..self.filter_len]{
+                    col +=1;
+                    self.vga[self.height-1][col] = *b as char;
+                }
+            }
+        }
+        if !self.is_being_edited && self.file_count > 0{
+            let mut end = self.width-2;
+            for (i, n) in [self.file_count, self.current_highlighted + 1].iter().enumerate(){
+                let mut n = *n;
+                loop{
+                    self.vga[self.height-1][end] = char::from_digit((n % 10) as u32, 10).unwrap();
+                    end -=1;
+                    n /= 10;
+                    if n == 0{
+                        break;
+                    }
+                }
+                if i == 0{
+                    self.vga[self.height-1][end] = '/';
+                    end -=1;
+                }
+            }
+        }
+        // Border cells not already highlighted take the focus color.
+        let border = if self.in_use { self.theme.focused_border } else { self.theme.unfocused_border };
+        for i in 0..self.height{
+            for j in 0..self.width{
+                let edge = i==0 || i == self.height-1 || j == 0 || j == self.width-1;
+                if edge && self.background[i][j] == self.theme.text.1{
+                    self.foreground[i][j] = border;
+                }
+            }
+        }
+        for i in 0..self.height-2{
+            for j in 0..self.width-2{
+                if self.selected[i][j]{
+                    self.foreground[i+1][j+1] = self.theme.selection.0;
+                    self.background[i+1][j+1] = self.theme.selection.1;
+                }
+            }
+        }
+    }
+
+    // Keywords, string literals and numbers in the visible text, one screen row at a time.
+    fn color_syntax(&mut self){
+        let (cols, rows) = self.content_size();
+        for r in 0..rows{
+            let row = self.contents[r];
+            let mut j = self.gutter_width();
+            while j < cols{
+                let start = j;
+                let color = if row[j] == '"'{
+                    j +=1;
+                    while j < cols && row[j] != '"' && row[j] != '\0'{
+                        j +=1;
+                    }
+                    j = (j+1).min(cols);
+                    Some(self.theme.string)
+                }
+                else if row[j].is_ascii_alphabetic(){
+                    while j < cols && (row[j].is_ascii_alphanumeric() || row[j] == '_'){
+                        j +=1;
+                    }
+                    let word = &row[start..j];
+                    KEYWORDS.iter().any(|k| k.chars().eq(word.iter().copied())).then_some(self.theme.keyword)
+                }
+                else if row[j].is_ascii_digit(){
+                    j +=1;
+                    Some(self.theme.number)
+                }
+                else{
+                    j +=1;
+                    None
+                };
+                if let Some(color) = color{
+                    for col in start..j{
+                        self.foreground[r+1][col+1] = color;
+                    }
+                }
+            }
+        }
     }
 
     pub fn start_editing(&mut self){
         self.reset_colors();
-            for i in 2..MAX_FILENAME_BYTES+4{
-                if i < 6{
-                    self.vga[0][i] = F6.as_bytes()[i-2] as char;
+            // "(F6) name RO" from column 2, stopping short of the F<n> label mid-border.
+            let title_limit = self.width/2 - 1;
+            for (i, c) in F6.chars().enumerate(){
+                self.vga[0][2+i] = c;
+            }
+            let name_len = self.current_file.iter().take_while(|b| **b != 0).count();
+            for (i, b) in self.current_file[..name_len].iter().enumerate(){
+                let col = 2 + F6.len() + i;
+                if col < title_limit{
+                    self.vga[0][col] = *b as char;
+                    self.foreground[0][col] = self.theme.highlight.0;
+                    self.background[0][col] = self.theme.highlight.1
                 }
-                else{
-                    let a = self.current_file[i-6] as char;
-                    if a.is_alphanumeric(){
-                        self.vga[0][i] = self.current_file[i-6] as char;
-                        self.foreground[0][i] = Color::Black;
-                        self.background[0][i] = Color::White
+            }
+            if self.editor_open{
+                // The title ends after the filename and its RO marker.
+                let mut title_end = (2 + F6.len() + name_len).min(title_limit);
+                if self.read_only && title_end + 3 <= title_limit{
+                    // One space past the end of the filename.
+                    self.vga[0][title_end+1] = 'R';
+                    self.vga[0][title_end+2] = 'O';
+                    title_end +=3;
+                }
+                self.color_syntax();
+                for cell in [self.brace_cell, self.match_cell]{
+                    if let Some((row, col)) = cell{
+                        self.foreground[row+1][col+1] = self.theme.brace_match.0;
+                        self.background[row+1][col+1] = self.theme.brace_match.1;
                     }
                 }
+                let (line, col) = self.cursor_line_col();
+                let mut readout = ['\0'; 2*20+1];
+                let mut writer = RowWriter{row: &mut readout, len: 0};
+                let _ = write!(writer, "{}:{}", line, col);
+                let len = writer.len;
+                // Leave at least a space after the title; drop the readout if it won't fit.
+                if title_end + 1 + len <= self.width-2{
+                    let start = self.width-1 - len;
+                    self.vga[0][start..start+len].copy_from_slice(&readout[..len]);
+                }
             }
 
     }
@@ -287,6 +1021,26 @@ impl Window {
 //         Self {  }
 //     }
 // }
+// What the top-row prompt does with the name typed into it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PromptAction{
+    Create,
+    Rename([u8; MAX_FILENAME_BYTES]),
+    Copy([u8; MAX_FILENAME_BYTES]),
+    MakeDirectory,
+    Open,
+    Run,
+}
+
+// A row of the file browser: the parent directory, a subdirectory, or a file by
+// its padded name, which stays right however the FileSystem's listing reorders.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Entry{
+    Parent,
+    Directory(usize),
+    File([u8; MAX_FILENAME_BYTES]),
+}
+
 pub struct Kernel {
     // YOUR CODE HERE
     screen: [[char; BUFFER_WIDTH]; BUFFER_HEIGHT],
@@ -301,9 +1055,9 @@ pub struct Kernel {
     user_input: [char; MAX_USER_INPUT_BYTES],
     current_user_input_index: usize,
     background_color: Color,
-    filesystem: FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES>,
+    filesystem: Box<Files>,
     //output: Output,
-    //current_processes: [Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE,MAX_HEAP_BLOCKS>>; 4],
+    //current_processes: [Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE,MAX_HEAP_BLOCKS>>; 4],
     total_ticks: usize,
     //current_outputs: [Output; 4],
     is_blocked: [bool;4],
@@ -316,9 +1070,9 @@ pub struct Kernel {
     bool_f2: (bool, bool),
     bool_f3: (bool, bool),
     //bool_f4: (bool, bool),
-    int_f1: Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>,
-    int_f2: Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>,
-    int_f3: Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>,
+    int_f1: Box<Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>>,
+    int_f2: Box<Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>>,
+    int_f3: Box<Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>>,
     running: bool,
     waiting: bool,
     input_flag: bool,
@@ -330,54 +1084,39 @@ pub struct Kernel {
     process_ran: usize,
     turn_index: usize,
     num_ticks: [usize; 4],
+    return_to_editor: [Option<(usize, usize)>; 4],
+    clipboard: Box<[u8; MAX_FILE_BYTES]>,
+    clipboard_len: usize,
+    pending_delete: Option<[u8; MAX_FILENAME_BYTES]>,
+    prompt_len: usize,
+    prompt_action: PromptAction,
+    prompt_window: usize,
+    modified: [([u8; MAX_FILENAME_BYTES], usize); MAX_FILES_STORED],
+    modification_count: usize,
+    directories: DirectoryTree,
+    attributes: Attributes,
+    // The IDE disk under the FileSystem, kept here for flushing; None on a RamDisk.
+    disk: Option<AtaDisk<BLOCK_SIZE, NUM_BLOCKS>>,
+    status_message: [char; BUFFER_WIDTH],
+    status_is_error: bool,
+    rate_ticks: usize,
+    rate_start: [usize; 4],
+    rates: [usize; 4],
+    presented: [[(char, Color, Color); BUFFER_WIDTH]; BUFFER_HEIGHT],
+    needs_render: bool,
+    layout: Layout,
+    previous_layout: Layout,
+    slots: [usize; NUM_WINDOWS],
+    theme: usize,
+    hardware_cursor: Option<(usize, usize)>,
+    modifiers: Modifiers,
+    more_hints: bool,
     
-    //int_f4: Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>,
+    //int_f4: Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE, MAX_HEAP_BLOCKS>>,
 }
 
-const HELLO: &str = r#"print("Hello, world!")"#;
-
-const NUMS: &str = r#"print(1)
-print(257)"#;
-
-const ADD_ONE: &str = r#"x := input("Enter a number")
-x := (x + 1)
-print(x)"#;
-
-const COUNTDOWN: &str = r#"count := input("count")
-while (count > 0) {
-    count := (count - 1)
-}
-print("done")
-print(count)"#;
-
-const AVERAGE: &str = r#"sum := 0
-count := 0
-averaging := true
-while averaging {
-    num := input("Enter a number:")
-    if (num == "quit") {
-        averaging := false
-    } else {
-        sum := (sum + num)
-        count := (count + 1)
-    }
-}
-print((sum / count))"#;
-
-const PI: &str = r#"sum := 0
-i := 0
-neg := false
-terms := input("Num terms:")
-while (i < terms) {
-    term := (1.0 / ((2.0 * i) + 1.0))
-    if neg {
-        term := -term
-    }
-    sum := (sum + term)
-    neg := not neg
-    i := (i + 1)
-}
-print((4 * sum))"#;
+// The files in programs/, packed by build.rs as INITIAL_FILES: &[(&str, &[u8])].
+include!(concat!(env!("OUT_DIR"), "/initial_files.rs"));
 
 /*
 When writing in file do we need to worry about enter button? YES
@@ -389,22 +1128,31 @@ By create data type, do you suggest a parameter in Kernel or a seperate struct.
 Look for \n explicitly
 
  */
+// A zeroed file-sized buffer, allocated on the heap without passing through the stack.
+fn file_buffer() -> Box<[u8; MAX_FILE_BYTES]>{
+    vec![0u8; MAX_FILE_BYTES].into_boxed_slice().try_into().unwrap()
+}
+
+// Allocate first, then build the value into the allocation, so structures as large
+// as the FileSystem and the interpreters are not assembled on the kernel stack and
+// copied over the way Box::new(value) does.
+#[inline(always)]
+fn boxed<T>(make: impl FnOnce() -> T) -> Box<T>{
+    let mut slot = Box::<T>::new_uninit();
+    slot.write(make());
+    unsafe { slot.assume_init() }
+}
+
+
 // Seed the disk with some programs.
-fn initial_files(disk: &mut FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES>) {
-    for (filename, contents) in [
-        ("hello", HELLO),
-        ("nums", NUMS),
-        ("add_one", ADD_ONE),
-        ("countdown", COUNTDOWN),
-        ("average", AVERAGE),
-        ("pi", PI),
-    ] {
-        let fd = disk.open_create(filename).unwrap();
-        disk.write(fd, contents.as_bytes()).unwrap();
-        disk.close(fd);
-        
+fn initial_files(disk: &mut Files) -> FileSystemResult<()> {
+    for (filename, contents) in INITIAL_FILES {
+        let fd = disk.open_create(filename)?;
+        disk.write(fd, contents)?;
+        disk.close(fd)?;
     }
     //panic!();
+    Ok(())
 }
 
 
@@ -422,8 +1170,24 @@ impl Kernel {
         for (i,c) in FILENAME_PROMPT.chars().enumerate(){
             user_input[i] = c;
         }
-        let mut filesystem: FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES> = FileSystem::new(ramdisk::RamDisk::new());
-        //let mut current_processes:Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, WINDOW_WIDTH, CopyingHeap<HEAP_SIZE,MAX_HEAP_BLOCKS>> = [Interpreter::new(""), 4];
+        // Mount the IDE disk's files, formatting it only when it holds no file system.
+        // A disk that cannot be read is never formatted over; files go on a RamDisk.
+        let drive = AtaDisk::new(Drive::Slave);
+        let mut unreadable = false;
+        let mut filesystem: Box<Files> = boxed(|| match drive{
+            Some(ata) => FileSystem::mount(Disk::Ata(ata)).unwrap_or_else(|_| {
+                if disk::has_error(){
+                    unreadable = true;
+                    FileSystem::new(Disk::Ram(RamDisk::new()))
+                }
+                else{
+                    FileSystem::new(Disk::Ata(ata))
+                }
+            }),
+            None => FileSystem::new(Disk::Ram(RamDisk::new())),
+        });
+        let disk = if unreadable { None } else { drive };
+        //let mut current_processes:Interpreter<MAX_TOKENS, MAX_LITERAL_CHARS, STACK_DEPTH, MAX_LOCAL_VARS, LINE_WIDTH, CopyingHeap<HEAP_SIZE,MAX_HEAP_BLOCKS>> = [Interpreter::new(""), 4];
         //let mut current_outputs = [Output::new(); 4];
         let mut is_blocked = [true;4];
         let mut is_running = [false;4];
@@ -433,9 +1197,9 @@ impl Kernel {
         let mut background = [[Color::Black;BUFFER_WIDTH];BUFFER_HEIGHT];
 
         //Self{screen, background_color: Color::Black, task_manager, top_row, quad_f1, quad_f2, quad_f3, quad_f4, user_is_typing: false, in_use, user_input, current_user_input_index: FILENAME_PROMPT.len(), filesystem, output: Output::new(), current_processes, current_outputs, total_ticks: 0, is_blocked, is_running, instructions_exectued }
-        let int_f1 = Interpreter::new("");
-        let int_f2 = Interpreter::new("");
-        let int_f3 = Interpreter::new("");
+        let int_f1 = boxed(|| Interpreter::new(""));
+        let int_f2 = boxed(|| Interpreter::new(""));
+        let int_f3 = boxed(|| Interpreter::new(""));
         //let int_f4 = Interpreter::new("");
         let mut bool_f1 = (false, false);
         let mut bool_f2 = (false, false);
@@ -444,7 +1208,7 @@ impl Kernel {
         let mut run_input = ['\0'; 20];
         let mut num_ticks = [0;4];
         //let mut bool_f4 = (false, false);
-        Self{screen, background_color: Color::Black, task_manager, top_row, quad_f1, quad_f2, quad_f3, quad_f4, user_is_typing: false, in_use, user_input, current_user_input_index: FILENAME_PROMPT.len(), filesystem,  total_ticks: 0, is_blocked, is_running,instructions_executed, foreground, background, editing: false, int_f1, int_f2, int_f3, bool_f1,bool_f2,bool_f3, running: false, waiting: false, input_flag, run_input, ri_index: 0, process_ran:0,turn_index:0,num_ticks,input_flag1: false, input_flag2: false, input_flag3: false}
+        Self{screen, background_color: Color::Black, task_manager, top_row, quad_f1, quad_f2, quad_f3, quad_f4, user_is_typing: false, in_use, user_input, current_user_input_index: FILENAME_PROMPT.len(), filesystem,  total_ticks: 0, is_blocked, is_running,instructions_executed, foreground, background, editing: false, int_f1, int_f2, int_f3, bool_f1,bool_f2,bool_f3, running: false, waiting: false, input_flag, run_input, ri_index: 0, process_ran:0,turn_index:0,num_ticks,input_flag1: false, input_flag2: false, input_flag3: false, return_to_editor: [None; 4], clipboard: file_buffer(), clipboard_len: 0, pending_delete: None, prompt_len: FILENAME_PROMPT.len(), prompt_action: PromptAction::Create, prompt_window: 0, modified: [([0; MAX_FILENAME_BYTES], 0); MAX_FILES_STORED], modification_count: 0, directories: DirectoryTree::new(), attributes: Attributes::new(), disk, status_message: ['\0'; BUFFER_WIDTH], status_is_error: false, rate_ticks: 0, rate_start: [0; 4], rates: [0; 4], presented: [[('\u{1}', Color::Black, Color::Black); BUFFER_WIDTH]; BUFFER_HEIGHT], needs_render: true, layout: Layout::Grid, previous_layout: Layout::Grid, slots: [1, 2, 3, 4], theme: 0, hardware_cursor: Some((BUFFER_HEIGHT, 0)), modifiers: Modifiers::default(), more_hints: false}
 
     }
 
@@ -476,10 +1240,31 @@ impl Kernel {
         // self.filesystem.write(f6, ADD_ONE.as_bytes()).unwrap();
         // self.filesystem.close(f6);
 
-        initial_files(&mut self.filesystem);
+        if let Some(e) = disk::take_error(){
+            self.show_error(format_args!("Cannot read the disk, files will not persist: {:?}", e));
+        }
+        // A formatted disk has the directory table; otherwise seed it for next boot.
+        if !self.file_exists(padded_name(TABLE_FILE).unwrap()){
+            if let Err(e) = initial_files(&mut self.filesystem){
+                self.show_error(format_args!("Cannot seed the disk: {:?}", e));
+            }
+            self.load_tree();
+            // Seeds start out locked; unlocking one with ^P is saved like any other lock.
+            for (filename, _) in INITIAL_FILES{
+                let u8_name = padded_name(filename).unwrap();
+                if self.file_exists(u8_name){
+                    self.attributes.set_locked(u8_name, true);
+                }
+            }
+            self.save_attributes();
+        }
+        else{
+            self.load_tree();
+            self.load_attributes();
+        }
 
 
-        // let u8_name =self.filesystem.list_directory().unwrap().1[3];
+        // let u8_name =self.list_files().1[3];
         // let str_name = core::str::from_utf8(&u8_name).unwrap();
         // println!("{str_name}");
         // let fd = self.filesystem.open_read("pi").unwrap();
@@ -497,13 +1282,14 @@ impl Kernel {
         // let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
         // let mut char_count = 0;
 
-        // for i in self.filesystem.list_directory().unwrap().1{
+        // for i in self.list_files().1{
         //     for j in i{
         //         content_buff[char_count] = j as char;
         //         char_count +=1;
         //     }
             
         // }
+        self.arrange_windows();
         for i in 1..5 as usize{
             self.in_use = i;
             self.default_window();
@@ -515,12 +1301,13 @@ impl Kernel {
 
     }
 
-    pub fn write_to_window(&mut self, window_num: usize, contents: [char; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)]){
+    pub fn write_to_window(&mut self, window_num: usize, contents: [char; MAX_CONTENT_CELLS]){
 
 
+        let (width, height) = (self.window(window_num).width, self.window(window_num).height);
         let mut char_count = 0;
         let mut row_count = 0;
-        let mut c = [['\0';WINDOW_WIDTH-2];WINDOW_HEIGHT-2];
+        let mut c = [['\0'; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
         for i in contents{
             if i=='\n'{
                 
@@ -528,15 +1315,16 @@ impl Kernel {
                 row_count +=1;
                 continue;
             }
-            let mut val = 5;
-            if (self.editing){
-                val = 2;
+            // Browser rows hold whole names; output uses the full width.
+            let mut limit = (width-5) / MAX_FILENAME_BYTES * MAX_FILENAME_BYTES;
+            if (self.window(window_num).is_being_edited){
+                limit = width-2;
             }
-            if char_count >= WINDOW_WIDTH-val{
+            if char_count >= limit{
                 char_count = 0;
                 row_count +=1;
             }
-            if row_count >=WINDOW_HEIGHT-2{
+            if row_count >=height-2{
                 break;
             }
             c[row_count][char_count] = i;
@@ -567,18 +1355,24 @@ impl Kernel {
         //println!("{:?}", contents);
     }
     pub fn key(&mut self, key: DecodedKey) {
+        self.clear_message();
         match key {
             DecodedKey::RawKey(code) => self.handle_raw(code),
             DecodedKey::Unicode(c) => self.handle_unicode(c)
         }
-        self.draw();
+        self.request_render();
     }
     pub fn update_in_use(&mut self, i: usize){
         self.quad_f1.set_in_use(false);
         self.quad_f2.set_in_use(false);
         self.quad_f3.set_in_use(false);
         self.quad_f4.set_in_use(false);
+        let previous = self.in_use;
         self.in_use = i;
+        self.editing = i != 0 && self.window(i).editor_open;
+        if i != 0 && self.window_rect(i).is_none(){
+            self.show_window(i, previous);
+        }
         if i == 1{
             self.quad_f1.set_in_use(true);
             
@@ -599,33 +1393,45 @@ impl Kernel {
 
 
     fn handle_raw(&mut self, key: KeyCode) {
+        // Any key other than 'y' keeps a file waiting on delete confirmation.
+        self.pending_delete = None;
         match key{
             KeyCode::F1=> {
-                if !self.editing{
-                    self.update_in_use(1);
-                    self.user_is_typing = false;
+                if self.editing{
+                    self.leave_editor();
                 }
+                self.update_in_use(1);
+                self.user_is_typing = false;
+                self.reset_prompt();
             }
             KeyCode::F2=> {
-                if !self.editing{
-                    self.update_in_use(2);
-                    self.user_is_typing = false;
+                if self.editing{
+                    self.leave_editor();
                 }
+                self.update_in_use(2);
+                self.user_is_typing = false;
+                self.reset_prompt();
             }
             KeyCode::F3=> {
-                if !self.editing{
-                    self.update_in_use(3);
-                    self.user_is_typing = false;
+                if self.editing{
+                    self.leave_editor();
                 }
+                self.update_in_use(3);
+                self.user_is_typing = false;
+                self.reset_prompt();
             }
             KeyCode::F4=> {
-                if !self.editing{
-                    self.update_in_use(4);
-                    self.user_is_typing = false;
+                if self.editing{
+                    self.leave_editor();
                 }
+                self.update_in_use(4);
+                self.user_is_typing = false;
+                self.reset_prompt();
             }
             KeyCode::F5=> {
                 if !self.editing {
+                    self.reset_prompt();
+                    self.prompt_window = self.in_use;
                     self.update_in_use(0);
                     self.user_is_typing = true;
                 }
@@ -650,7 +1456,7 @@ impl Kernel {
                         self.wait_check();
                         self.ri_index = 0;
                         self.run_input = ['\0';20];
-                        let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
+                        let empty = ['\0'; MAX_CONTENT_CELLS];
                         self.write_to_window(1, empty);
                         self.num_ticks[0] = 0;
 
@@ -661,7 +1467,7 @@ impl Kernel {
                         self.wait_check();
                         self.ri_index = 0;
                         self.run_input = ['\0';20];
-                        let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
+                        let empty = ['\0'; MAX_CONTENT_CELLS];
                         self.write_to_window(2, empty);
                         self.num_ticks[1] = 0;
                     }
@@ -671,7 +1477,7 @@ impl Kernel {
                         self.wait_check();
                         self.ri_index = 0;
                         self.run_input = ['\0';20];
-                        let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
+                        let empty = ['\0'; MAX_CONTENT_CELLS];
                         self.write_to_window(3, empty);
                         self.num_ticks[2] = 0;
                     }
@@ -690,28 +1496,106 @@ impl Kernel {
                     self.default_window();
                 }
             }
+            KeyCode::PageDown=>{
+                if self.browsing(){
+                    self.page_down();
+                }
+            }
+            KeyCode::PageUp=>{
+                if self.browsing(){
+                    self.page_up();
+                }
+            }
+            KeyCode::F7=>{
+                if self.editing{
+                    self.run_from_editor();
+                }
+            }
+            KeyCode::F8=>{
+                if self.in_use != 0{
+                    self.window_mut(self.in_use).toggle_selection();
+                    self.update_screen();
+                }
+            }
+            KeyCode::F9=>{
+                self.copy_selection(false);
+            }
+            KeyCode::F10=>{
+                self.copy_selection(true);
+            }
+            KeyCode::F11=>{
+                self.paste_clipboard();
+            }
+            KeyCode::F12=>{
+                if self.modifiers.shift{
+                    self.next_layout();
+                }
+                else{
+                    self.toggle_maximized();
+                }
+            }
             KeyCode::ArrowLeft=>{
                 if !self.editing{
                     self.move_left();
                 }
+                else{
+                    let shift = self.modifiers.shift;
+                    let window = self.window_mut(self.in_use);
+                    window.before_cursor_move(shift);
+                    window.edit_move_left();
+                    window.render_editor();
+                    self.update_screen();
+                }
             }
             KeyCode::ArrowRight=>{
                  
                 if !self.editing{
                     self.move_right();
                 }
+                else{
+                    let shift = self.modifiers.shift;
+                    let window = self.window_mut(self.in_use);
+                    window.before_cursor_move(shift);
+                    window.edit_move_right();
+                    window.render_editor();
+                    self.update_screen();
+                }
             }
             KeyCode::ArrowDown=>{
-               
-                if !self.editing{
+                self.shift_line_selection();
+                if !self.editing && self.line_selecting(){
+                    self.window_mut(self.in_use).move_line_selection(true);
+                    self.update_screen();
+                }
+                else if !self.editing{
                     self.move_down();
                 }
+                else{
+                    let shift = self.modifiers.shift;
+                    let window = self.window_mut(self.in_use);
+                    window.before_cursor_move(shift);
+                    window.edit_move_down();
+                    window.render_editor();
+                    self.update_screen();
+                }
             }
             KeyCode::ArrowUp=>{
-                
-                if !self.editing&& !self.running{
+                self.shift_line_selection();
+                if !self.editing && self.line_selecting(){
+                    self.window_mut(self.in_use).move_line_selection(false);
+                    self.update_screen();
+                }
+                else if !self.editing&& !self.running{
                     self.move_up();
                 }
+                else if self.editing{
+                    let shift = self.modifiers.shift;
+                    let window = self.window_mut(self.in_use);
+                    window.before_cursor_move(shift);
+                    window.edit_move_up();
+                    window.render_editor();
+                    self.update_screen();
+                }
             }
             
 
@@ -721,297 +1605,602 @@ impl Kernel {
 
 
     pub fn default_window(&mut self){
-        let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-        let mut char_count = 0;
-        for i in self.filesystem.list_directory().unwrap().1{
-            for j in i{
-                content_buff[char_count] = j as char;
-                char_count +=1;
-            }
-            
+        if self.in_use == 0{
+            return;
+        }
+        let window = self.window_mut(self.in_use);
+        window.clear_selection();
+        window.is_being_edited = false;
+        window.reset_colors();
+        window.update_borders();
+        window.find_contents_index();
+        let content_buff = self.listing_for(self.in_use);
+        self.write_to_window(self.in_use, content_buff);
+    }
+    // Save and close the focused editor, back to the file grid. An editor whose save
+    // fails stays open with its buffer; false then.
+    pub fn shut_off_editing(&mut self) -> bool{
+        if self.in_use == 0{
+            return false;
+        }
+        if !self.window(self.in_use).read_only && !self.save_window(self.in_use){
+            return false;
+        }
+        self.editing = false;
+        let window = self.window_mut(self.in_use);
+        window.close_editor();
+        window.reset_colors();
+        window.update_borders();
+        window.find_contents_index();
+        let content_buff = self.listing_for(self.in_use);
+        self.write_to_window(self.in_use, content_buff);
+        true
+    }
+
+    // Focus is moving off an open editor: the editor stays open, but its buffer is
+    // saved first so other windows opening or running the file see the edits.
+    pub fn leave_editor(&mut self){
+        if self.in_use != 0 && !self.window(self.in_use).read_only{
+            self.save_window(self.in_use);
         }
+    }
 
-        if self.in_use ==1{
-            self.quad_f1.is_being_edited = false;
-            self.quad_f1.reset_colors();
-            self.quad_f1.update_borders();
-            self.quad_f1.find_contents_index();
-            self.write_to_window(1, content_buff);
-        }
-        else if self.in_use ==2{
-            self.quad_f2.is_being_edited = false;
-            self.quad_f2.reset_colors();
-            self.quad_f2.update_borders();
-            self.quad_f2.find_contents_index();
-            self.write_to_window(2, content_buff);
-        }
-        else if self.in_use ==3{
-            self.quad_f3.is_being_edited = false;
-            self.quad_f3.reset_colors();
-            self.quad_f3.update_borders();
-            self.quad_f3.find_contents_index();
-            self.write_to_window(3, content_buff);
-        }
-        else if self.in_use ==4{
-            self.quad_f4.is_being_edited = false;
-            self.quad_f4.reset_colors();
-            self.quad_f4.update_borders();
-            self.quad_f4.find_contents_index();
-            self.write_to_window(3, content_buff);
-        }
-    }
-    pub fn shut_off_editing(&mut self){
-        if self.in_use==1{
-            let filename = core::str::from_utf8(&self.quad_f1.current_file).unwrap();
-            let fd = self.filesystem.open_create(filename).unwrap();
-            
-            self.filesystem.write(fd, &self.quad_f1.all_contents_u8);
-            self.filesystem.close(fd);
-            self.editing = false;
-            let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            let mut char_count = 0;
-
-            for i in self.filesystem.list_directory().unwrap().1{
-                for j in i{
-                    content_buff[char_count] = j as char;
-                    char_count +=1;
-                }
-                
+    // Write a window's editor buffer to its file; false after reporting an error.
+    pub fn save_window(&mut self, window_num: usize) -> bool{
+        let window = match window_num{
+            1 => &self.quad_f1,
+            2 => &self.quad_f2,
+            3 => &self.quad_f3,
+            _ => &self.quad_f4,
+        };
+        let (u8_name, contents, len) = (window.current_file, window.all_contents_u8.clone(), window.file_len);
+        if !self.write_file(core::str::from_utf8(&u8_name).unwrap(), &contents[..len]){
+            return false;
+        }
+        self.touch(u8_name);
+        self.flush_disk();
+        true
+    }
+
+    // The FileSystem's listing, or an empty one after reporting why it failed.
+    pub fn list_files(&mut self) -> (usize, [[u8; MAX_FILENAME_BYTES]; MAX_FILES_STORED]){
+        match self.filesystem.list_directory(){
+            Ok(listing) => listing,
+            Err(e) => {
+                self.show_error(format_args!("Cannot list files: {:?}", e));
+                (0, [[0; MAX_FILENAME_BYTES]; MAX_FILES_STORED])
             }
-            self.quad_f1.is_being_edited = false;
-            self.quad_f1.reset_colors();
-            self.quad_f1.update_borders();
-            self.quad_f1.find_contents_index();
-            //panic!();
-            self.write_to_window(1, content_buff);
-            
         }
-        else if self.in_use==2{
-            let filename = core::str::from_utf8(&self.quad_f2.current_file).unwrap();
-            let fd = self.filesystem.open_create(filename).unwrap();
-            self.filesystem.write(fd, &self.quad_f2.all_contents_u8);
-            self.filesystem.close(fd);
-            self.editing = false;
-            let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            let mut char_count = 0;
-
-            for i in self.filesystem.list_directory().unwrap().1{
-                for j in i{
-                    content_buff[char_count] = j as char;
-                    char_count +=1;
-                }
-                
+    }
+
+    // Read a whole file into buffer, returning its length; None after reporting an error.
+    pub fn read_file(&mut self, name: &str, buffer: &mut [u8]) -> Option<usize>{
+        let result = self.filesystem.open_read(name).and_then(|fd| {
+            let len = self.filesystem.read(fd, buffer);
+            let _ = self.filesystem.close(fd);
+            len
+        });
+        if let Some(e) = disk::take_error(){
+            self.show_error(format_args!("Cannot read {}: {:?}", name.trim_end_matches('\0'), e));
+            return None;
+        }
+        match result{
+            Ok(len) => Some(len),
+            Err(e) => {
+                self.show_error(format_args!("Cannot read {}: {:?}", name.trim_end_matches('\0'), e));
+                None
             }
-            self.quad_f2.is_being_edited = false;
-            self.quad_f2.reset_colors();
-            self.quad_f2.update_borders();
-            self.write_to_window(2, content_buff);
-            
         }
-        else if self.in_use==3{
-            let filename = core::str::from_utf8(&self.quad_f3.current_file).unwrap();
-            let fd = self.filesystem.open_create(filename).unwrap();
-            self.filesystem.write(fd, &self.quad_f3.all_contents_u8);
-            self.filesystem.close(fd);
-            self.editing = false;
-            let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            let mut char_count = 0;
-
-            for i in self.filesystem.list_directory().unwrap().1{
-                for j in i{
-                    content_buff[char_count] = j as char;
-                    char_count +=1;
+    }
+
+    // Replace a file's contents, creating it if needed; false after reporting an error.
+    pub fn write_file(&mut self, name: &str, contents: &[u8]) -> bool{
+        // Rewritten in place and only once the new contents are known to fit, so a
+        // failed save leaves the old file, and a shorter one keeps none of its tail.
+        match self.filesystem.replace(name, contents){
+            Ok(()) => true,
+            Err(e) => {
+                self.show_error(format_args!("Cannot write {}: {:?}", name.trim_end_matches('\0'), e));
+                false
+            }
+        }
+    }
+
+    // FileSystem keeps no timestamps, so the kernel records the order files were written in.
+    pub fn touch(&mut self, u8_name: [u8; MAX_FILENAME_BYTES]){
+        self.modification_count +=1;
+        let slot = self.modified.iter().position(|(name, _)| *name == u8_name)
+            .or_else(|| self.modified.iter().position(|(name, _)| name[0] == 0));
+        if let Some(slot) = slot{
+            self.modified[slot] = (u8_name, self.modification_count);
+        }
+    }
+
+    pub fn modified_stamp(&self, u8_name: [u8; MAX_FILENAME_BYTES]) -> usize{
+        self.modified.iter().find(|(name, _)| *name == u8_name).map_or(0, |(_, stamp)| *stamp)
+    }
+
+    pub fn window(&self, window_num: usize) -> &Window{
+        match window_num{
+            1 => &self.quad_f1,
+            2 => &self.quad_f2,
+            3 => &self.quad_f3,
+            _ => &self.quad_f4,
+        }
+    }
+
+    pub fn window_mut(&mut self, window_num: usize) -> &mut Window{
+        match window_num{
+            1 => &mut self.quad_f1,
+            2 => &mut self.quad_f2,
+            3 => &mut self.quad_f3,
+            _ => &mut self.quad_f4,
+        }
+    }
+
+    // The 10-character label a browser entry is shown with.
+    fn entry_label(&self, entry: Entry) -> [u8; MAX_FILENAME_BYTES]{
+        let mut label = [0; MAX_FILENAME_BYTES];
+        match entry{
+            Entry::Parent => label[..2].copy_from_slice(b".."),
+            Entry::Directory(dir) => {
+                label = self.directories.name(dir);
+                if let Some(end) = label.iter().position(|b| *b == 0){
+                    label[end] = b'/';
                 }
-                
             }
-            self.quad_f3.is_being_edited = false;
-            self.quad_f3.reset_colors();
-            self.quad_f3.update_borders();
-            self.write_to_window(3, content_buff);
-            
+            Entry::File(name) => label = name,
         }
-        else if self.in_use==4{
-            let filename = core::str::from_utf8(&self.quad_f4.current_file).unwrap();
-            let fd = self.filesystem.open_create(filename).unwrap();
-            self.filesystem.write(fd, &self.quad_f4.all_contents_u8);
-            self.filesystem.close(fd);
-            self.editing = false;
-            let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            let mut char_count = 0;
-
-            for i in self.filesystem.list_directory().unwrap().1{
-                for j in i{
+        label
+    }
+
+    pub fn directory_contents(&mut self, view: &[Entry], offset: usize) -> [char; MAX_CONTENT_CELLS]{
+        let mut content_buff = ['\0'; MAX_CONTENT_CELLS];
+        let mut char_count = 0;
+        for entry in &view[offset.min(view.len())..]{
+            for j in self.entry_label(*entry){
+                if char_count < content_buff.len(){
                     content_buff[char_count] = j as char;
                     char_count +=1;
                 }
-                
             }
-            self.quad_f4.is_being_edited = false;
-            self.quad_f4.reset_colors();
-            self.quad_f4.update_borders();
-            self.write_to_window(4, content_buff);
-            
         }
+        content_buff
     }
 
-    pub fn move_left(&mut self){
-        if self.in_use ==1{
-            if self.quad_f1.current_highlighted >0{
-                self.quad_f1.current_highlighted-=1;
-                self.quad_f1.update_colors();
+    // What a window's browser shows: "..", then subdirectories by name, then the
+    // directory's files matching its filter in its sort order.
+    pub fn view_for(&mut self, window_num: usize) -> (usize, [Entry; MAX_ENTRIES]){
+        let (count, names) = self.list_files();
+        let window = self.window(window_num);
+        let (filter, filter_len, sort, dir) = (window.filter, window.filter_len, window.sort, window.current_dir);
+        let matches = |name: &[u8]| filter_len == 0 || name.windows(filter_len).any(|w| w.eq_ignore_ascii_case(&filter[..filter_len]));
+        let mut view = [Entry::Parent; MAX_ENTRIES];
+        let mut view_len = 0;
+        if dir != ROOT{
+            view_len = 1;
+        }
+        let first_directory = view_len;
+        for d in self.directories.children(dir){
+            if matches(&self.directories.name(d)){
+                view[view_len] = Entry::Directory(d);
+                view_len +=1;
+            }
+        }
+        view[first_directory..view_len].sort_unstable_by_key(|e| match e{
+            Entry::Directory(d) => self.directories.name(*d),
+            _ => [0; MAX_FILENAME_BYTES],
+        });
+        // Each listed file's sort key is taken once, then ties fall back to the
+        // FileSystem's own order, which is creation order.
+        let mut files = [(0, [0; MAX_FILENAME_BYTES], 0); MAX_FILES_STORED];
+        let mut file_count = 0;
+        for (i, name) in names[..count].iter().enumerate(){
+            if name[0] != b'.' && self.directories.parent_of_file(*name) == dir && matches(name){
+                let key = match sort{
+                    SortOrder::Size => usize::MAX - self.file_size(*name),
+                    SortOrder::Modified => usize::MAX - self.modified_stamp(*name),
+                    _ => 0,
+                };
+                files[file_count] = (key, if sort == SortOrder::Name { *name } else { [0; MAX_FILENAME_BYTES] }, i);
+                file_count +=1;
+            }
+        }
+        files[..file_count].sort_unstable();
+        for (_, _, i) in &files[..file_count]{
+            view[view_len] = Entry::File(names[*i]);
+            view_len +=1;
+        }
+        (view_len, view)
+    }
+
+    // The browser listing for a window, paged so its highlighted file is visible.
+    pub fn listing_for(&mut self, window_num: usize) -> [char; MAX_CONTENT_CELLS]{
+        let (count, view) = self.view_for(window_num);
+        let window = self.window_mut(window_num);
+        window.file_count = count;
+        window.view = view;
+        if window.current_highlighted >= count{
+            window.current_highlighted = count.saturating_sub(1);
+        }
+        window.scroll_to_highlight();
+        let (details, offset, filtered) = (window.details, window.list_offset, window.filter_len > 0);
+        let mut locked = [false; MAX_GRID_NAMES];
+        for (k, entry) in view[offset.min(count)..count].iter().take(locked.len()).enumerate(){
+            if let Entry::File(name) = entry{
+                locked[k] = self.attributes.is_locked(*name);
+            }
+        }
+        self.window_mut(window_num).locked = locked;
+        if count == 0{
+            let mut content_buff = ['\0'; MAX_CONTENT_CELLS];
+            let mut writer = RowWriter{row: &mut content_buff, len: 0};
+            if filtered{
+                let _ = write!(writer, "No files match the filter.\nEsc clears it.");
             }
-        }
-        else if self.in_use ==2{
-            if self.quad_f2.current_highlighted >0{
-                self.quad_f2.current_highlighted-=1;
-                self.quad_f2.update_colors();
+            else{
+                let _ = write!(writer, "No files.\nPress F5 to create one.");
             }
+            content_buff
         }
-        else if self.in_use ==3{
-            if self.quad_f3.current_highlighted >0{
-                self.quad_f3.current_highlighted-=1;
-                self.quad_f3.update_colors();
-            }
+        else if details{
+            let rows = self.window(window_num).page_rows();
+            self.details_contents(&view[..count], offset, rows)
         }
-        else if self.in_use ==4{
-            if self.quad_f4.current_highlighted >0{
-                self.quad_f4.current_highlighted-=1;
-                self.quad_f4.update_colors();
-            }
+        else{
+            self.directory_contents(&view[..count], offset)
         }
     }
-    pub fn move_right(&mut self){
-        if self.in_use ==1{
-            if self.quad_f1.current_highlighted <MAX_FILES_STORED-1{
-                self.quad_f1.current_highlighted+=1;
-                self.quad_f1.update_colors();
+
+    pub fn refresh_listing(&mut self, window_num: usize){
+        let content_buff = self.listing_for(window_num);
+        self.write_to_window(window_num, content_buff);
+    }
+
+    // One file per row with its size in bytes and blocks, and a disk usage footer.
+    // The free count comes from the FileSystem's block bitmap, so its own bitmaps
+    // and inode table are never counted as free.
+    pub fn details_contents(&mut self, view: &[Entry], offset: usize, rows: usize) -> [char; MAX_CONTENT_CELLS]{
+        let mut content_buff = ['\0'; MAX_CONTENT_CELLS];
+        let mut writer = RowWriter{row: &mut content_buff, len: 0};
+        let count = self.list_files().0;
+        let mut shown = 0;
+        for entry in &view[offset.min(view.len())..]{
+            if shown == rows{
+                break;
             }
-        }
-        else if self.in_use ==2{
-            if self.quad_f2.current_highlighted <MAX_FILES_STORED-1{
-                self.quad_f2.current_highlighted+=1;
-                self.quad_f2.update_colors();
+            let label = self.entry_label(*entry);
+            let str_name = core::str::from_utf8(&label).unwrap().trim_end_matches('\0');
+            if let Entry::File(name) = entry{
+                let size = self.file_size(*name);
+                let marker = if self.attributes.is_locked(*name) { "RO" } else { "" };
+                let _ = write!(writer, "{:<10} {:>5}B {:>2}blk {}\n", str_name, size, size.div_ceil(BLOCK_SIZE), marker);
             }
-        }
-        else if self.in_use ==3{
-            if self.quad_f3.current_highlighted <MAX_FILES_STORED-1{
-                self.quad_f3.current_highlighted+=1;
-                self.quad_f3.update_colors();
+            else{
+                let _ = write!(writer, "{:<10}  <dir>\n", str_name);
             }
+            shown +=1;
         }
-        else if self.in_use ==4{
-            if self.quad_f4.current_highlighted <MAX_FILES_STORED-1{
-                self.quad_f4.current_highlighted+=1;
-                self.quad_f4.update_colors();
-            }
+        for _ in shown..rows{
+            let _ = write!(writer, "\n");
         }
+        let _ = write!(writer, "free {}blk {}ino cap {}B", self.filesystem.num_free_blocks(), MAX_FILES_STORED - count, NUM_BLOCKS * BLOCK_SIZE);
+        content_buff
     }
-    pub fn move_down(&mut self){
-        if self.in_use ==1{
-            if self.quad_f1.current_highlighted <MAX_FILES_STORED-2-1{
-                self.quad_f1.current_highlighted+=3;
-                self.quad_f1.update_colors();
-            }
+
+    pub fn change_directory(&mut self, dir: usize){
+        let name = self.directories.name(dir);
+        let window = self.window_mut(self.in_use);
+        window.current_dir = dir;
+        window.dir_name = name;
+        window.current_highlighted = 0;
+        window.list_offset = 0;
+        window.filter_len = 0;
+        self.refresh_listing(self.in_use);
+    }
+
+    // Enter on a browser entry: move between directories, or edit a file.
+    pub fn open_highlighted(&mut self){
+        let current = self.window(self.in_use).current_dir;
+        match self.highlighted_entry(){
+            Some(Entry::Parent) => self.change_directory(self.directories.parent(current)),
+            Some(Entry::Directory(dir)) => self.change_directory(dir),
+            Some(Entry::File(_)) => self.edit_file_setup(),
+            None => {}
         }
-        else if self.in_use ==2{
-            if self.quad_f2.current_highlighted <MAX_FILES_STORED-2-1{
-                self.quad_f2.current_highlighted+=3;
-                self.quad_f2.update_colors();
-            }
+    }
+
+    pub fn remove_directory(&mut self, dir: usize){
+        let name = self.directories.name(dir);
+        let name = core::str::from_utf8(&name).unwrap().trim_end_matches('\0');
+        if !self.directories.is_empty(dir){
+            self.show_error(format_args!("{} is not empty", name));
+            return;
+        }
+        let parent = self.directories.parent(dir);
+        let parent_name = self.directories.name(parent);
+        self.directories.remove(dir);
+        for i in 1..5{
+            let window = self.window_mut(i);
+            if window.current_dir == dir{
+                window.current_dir = parent;
+                window.dir_name = parent_name;
+            }
+        }
+        self.save_tree();
+        self.refresh_listings();
+        self.show_message(format_args!("Removed {}", name));
+    }
+
+    // Every change to the file namespace ends here, so it also updates the disk.
+    pub fn save_tree(&mut self){
+        let mut table = [0u8; TABLE_BYTES];
+        self.directories.to_bytes(&mut table);
+        if self.write_file(TABLE_FILE, &table){
+            self.flush_disk();
         }
-        else if self.in_use ==3{
-            if self.quad_f3.current_highlighted <MAX_FILES_STORED-2-1{
-                self.quad_f3.current_highlighted+=3;
-                self.quad_f3.update_colors();
-            }
+    }
+
+    // Make the IDE disk's cached writes stick, and report anything the disk failed
+    // since the last check.
+    pub fn flush_disk(&mut self){
+        if let Some(drive) = self.disk.as_mut(){
+            disk::latch(drive.flush());
         }
-        else if self.in_use ==4{
-            if self.quad_f4.current_highlighted <MAX_FILES_STORED-2-1{
-                self.quad_f4.current_highlighted+=3;
-                self.quad_f4.update_colors();
-            }
+        if let Some(e) = disk::take_error(){
+            self.show_error(format_args!("Disk error: {:?}", e));
         }
     }
-    pub fn move_up(&mut self){
-        if self.in_use ==1{
-            if self.quad_f1.current_highlighted >2{
-                self.quad_f1.current_highlighted-=3;
-                self.quad_f1.update_colors();
-            }
+
+    pub fn save_attributes(&mut self){
+        let mut table = [0u8; ATTRIBUTES_BYTES];
+        self.attributes.to_bytes(&mut table);
+        if self.write_file(ATTRIBUTES_FILE, &table){
+            self.flush_disk();
         }
-        else if self.in_use ==2{
-            if self.quad_f2.current_highlighted >2{
-                self.quad_f2.current_highlighted-=3;
-                self.quad_f2.update_colors();
-            }
+    }
+
+    pub fn load_attributes(&mut self){
+        if self.file_exists(padded_name(ATTRIBUTES_FILE).unwrap()){
+            let mut table = vec![0u8; MAX_FILE_BYTES];
+            let len = self.read_file(ATTRIBUTES_FILE, &mut table).unwrap_or(0);
+            self.attributes = Attributes::from_bytes(&table[..len]);
         }
-        else if self.in_use ==3{
-            if self.quad_f3.current_highlighted >2{
-                self.quad_f3.current_highlighted-=3;
-                self.quad_f3.update_colors();
+    }
+
+    // Read-only files can be viewed and run but not saved, renamed or deleted.
+    pub fn refuse_locked(&mut self, u8_name: [u8; MAX_FILENAME_BYTES]) -> bool{
+        if !self.attributes.is_locked(u8_name){
+            return false;
+        }
+        let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+        self.show_error(format_args!("{} is read-only (^P unlocks)", name));
+        true
+    }
+
+    pub fn toggle_locked(&mut self){
+        let u8_name = match self.highlighted_file(true){
+            Some(u8_name) => u8_name,
+            None => return,
+        };
+        let locked = !self.attributes.is_locked(u8_name);
+        if !self.attributes.set_locked(u8_name, locked){
+            self.show_error(format_args!("No room to lock another file"));
+            return;
+        }
+        self.save_attributes();
+        self.refresh_listings();
+        let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+        if locked{
+            self.show_message(format_args!("{} is now read-only", name));
+        }
+        else{
+            self.show_message(format_args!("{} is now writable", name));
+        }
+    }
+
+    pub fn load_tree(&mut self){
+        if self.file_exists(padded_name(TABLE_FILE).unwrap()){
+            let mut table = vec![0u8; MAX_FILE_BYTES];
+            let len = self.read_file(TABLE_FILE, &mut table).unwrap_or(0);
+            self.directories = DirectoryTree::from_bytes(&table[..len]);
+        }
+        else{
+            self.save_tree();
+        }
+    }
+
+    // Type-ahead filter keys: name characters narrow the listing, Backspace erases
+    // one and Esc clears the filter.
+    pub fn filter_key(&mut self, key: char){
+        let window = self.window_mut(self.in_use);
+        match key{
+            '\u{1b}' => window.filter_len = 0,
+            '\u{8}' => window.filter_len = window.filter_len.saturating_sub(1),
+            _ => {
+                if window.filter_len < MAX_FILENAME_BYTES{
+                    window.filter[window.filter_len] = key as u8;
+                    window.filter_len +=1;
+                }
             }
         }
-        else if self.in_use ==4{
-            if self.quad_f4.current_highlighted >2{
-                self.quad_f4.current_highlighted-=3;
-                self.quad_f4.update_colors();
+        window.current_highlighted = 0;
+        self.refresh_listing(self.in_use);
+    }
+
+    // The length kept in the file's inode, so nothing is read; 0 after reporting an error.
+    pub fn file_size(&mut self, u8_name: [u8; MAX_FILENAME_BYTES]) -> usize{
+        let name = core::str::from_utf8(&u8_name).unwrap();
+        match self.filesystem.file_size(name){
+            Ok(size) => size,
+            Err(e) => {
+                self.show_error(format_args!("Cannot stat {}: {:?}", name.trim_end_matches('\0'), e));
+                0
             }
         }
     }
 
+    // Move the browser highlight, redrawing the listing when it crosses a page.
+    fn set_highlight(&mut self, highlighted: usize){
+        if self.in_use == 0{
+            return;
+        }
+        let window = self.window_mut(self.in_use);
+        let offset = window.list_offset;
+        window.current_highlighted = highlighted;
+        window.scroll_to_highlight();
+        window.update_colors();
+        if window.list_offset != offset && !window.is_being_edited{
+            self.refresh_listing(self.in_use);
+        }
+    }
+    pub fn move_left(&mut self){
+        let highlighted = self.window(self.in_use).current_highlighted;
+        self.set_highlight(highlighted.saturating_sub(1));
+    }
+    pub fn move_right(&mut self){
+        let window = self.window(self.in_use);
+        let (highlighted, count) = (window.current_highlighted, window.file_count);
+        if highlighted + 1 < count{
+            self.set_highlight(highlighted + 1);
+        }
+    }
+    pub fn move_down(&mut self){
+        let window = self.window(self.in_use);
+        let (highlighted, count, step) = (window.current_highlighted, window.file_count, window.grid_columns());
+        if highlighted + step < count{
+            self.set_highlight(highlighted + step);
+        }
+    }
+    pub fn move_up(&mut self){
+        let window = self.window(self.in_use);
+        let (highlighted, step) = (window.current_highlighted, window.grid_columns());
+        if highlighted >= step{
+            self.set_highlight(highlighted - step);
+        }
+    }
+    pub fn page_down(&mut self){
+        let window = self.window(self.in_use);
+        let (highlighted, count) = (window.current_highlighted, window.file_count);
+        let page = window.page_rows() * window.grid_columns();
+        self.set_highlight((highlighted + page).min(count.saturating_sub(1)));
+    }
+    pub fn page_up(&mut self){
+        let window = self.window(self.in_use);
+        let (highlighted, page) = (window.current_highlighted, window.page_rows() * window.grid_columns());
+        self.set_highlight(highlighted.saturating_sub(page));
+    }
+
     fn handle_unicode(&mut self, key: char) {
-        if self.user_is_typing && key.is_alphanumeric(){  //Probably need to change is_alphanumeric
-            if self.current_user_input_index < (MAX_USER_INPUT_BYTES){
+        if self.pending_delete.is_some(){
+            self.confirm_delete(key);
+            return;
+        }
+        let mut key = key;
+        if self.browsing(){
+            let filter_len = self.window(self.in_use).filter_len;
+            match self.browser_command(key){
+                Some(command) => key = command,
+                None if is_name_char(key) || key=='\u{1b}' || (key=='\u{8}' && filter_len > 0) => {
+                    self.filter_key(key);
+                    return;
+                }
+                None => (),
+            }
+        }
+        if self.user_is_typing && (is_name_char(key) || key=='/'){
+            if self.current_user_input_index < (self.prompt_len + MAX_PATH_BYTES).min(MAX_USER_INPUT_BYTES){
                 self.user_input[self.current_user_input_index] = key;
                 self.current_user_input_index +=1;
                 self.update_screen();
             }
         }
-        else if self.user_is_typing && self.current_user_input_index>FILENAME_PROMPT.len() && key=='\u{8}'{  
+        else if self.user_is_typing && self.current_user_input_index>self.prompt_len && key=='\u{8}'{  
             self.user_input[self.current_user_input_index-1] = '\0';
             self.current_user_input_index -=1;
             self.update_screen();
         }
         else if (self.user_is_typing && key=='\n'){
-            let mut temp_buff = [0 as u8; MAX_FILENAME_BYTES];
-            let p_name = &self.user_input[FILENAME_PROMPT.len()..self.user_input.len()];
-
-            for (i,c) in p_name.iter().enumerate(){
-                temp_buff[i] = *c as u8;
-            }
-            let mut program = core::str::from_utf8(&temp_buff).unwrap();
-            println!("{:?}",program);
-            let fd = self.filesystem.open_create(program).unwrap();
-            println!("1");
-            self.filesystem.close(fd);
-            for i in 0..MAX_FILENAME_BYTES{
-                self.handle_unicode('\u{8}');
-            }
-            let mut content_buff = ['\0'; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            let mut char_count = 0;
-            for i in self.filesystem.list_directory().unwrap().1{
-                for j in i{
-                    content_buff[char_count] = j as char;
-                    char_count +=1;
-                }
-                
-            }
-            for i in 1..5 as usize{
-                self.write_to_window(i, content_buff);
-            }
-            
-            // TODO WEIRD ERRROR MINUS 
+            self.submit_prompt();
         } 
         
         else if (!self.user_is_typing && !self.editing && key=='r'){
             self.run_file();
         }
+        else if (self.browsing() && key=='\n'){
+            self.open_highlighted();
+        }
+        else if (self.browsing() && key=='\u{8}'){
+            let current = self.window(self.in_use).current_dir;
+            if current != ROOT{
+                self.change_directory(self.directories.parent(current));
+            }
+        }
+        else if (self.browsing() && key=='m'){
+            self.start_prompt(MKDIR_PROMPT, PromptAction::MakeDirectory);
+        }
+        else if (self.browsing() && key=='o'){
+            self.start_prompt(OPEN_PROMPT, PromptAction::Open);
+        }
+        else if (self.browsing() && key=='x'){
+            self.start_prompt(RUN_PROMPT, PromptAction::Run);
+        }
+        else if (self.browsing() && key=='d'){
+            self.delete_highlighted();
+        }
+        else if (self.browsing() && key=='n'){
+            if let Some(u8_name) = self.highlighted_file(true){
+                if !self.refuse_locked(u8_name){
+                    self.start_prompt(RENAME_PROMPT, PromptAction::Rename(u8_name));
+                }
+            }
+        }
+        else if (self.browsing() && key=='p'){
+            self.toggle_locked();
+        }
+        else if (self.browsing() && key=='c'){
+            if let Some(u8_name) = self.highlighted_file(false){
+                self.start_prompt(COPY_PROMPT, PromptAction::Copy(u8_name));
+            }
+        }
+        else if (self.browsing() && key=='s'){
+            let window = self.window_mut(self.in_use);
+            window.sort = window.sort.next();
+            self.refresh_listing(self.in_use);
+        }
+        else if (self.browsing() && key=='v'){
+            let window = self.window_mut(self.in_use);
+            window.details = !window.details;
+            window.list_offset = 0;
+            self.default_window();
+        }
+        else if (self.browsing() && key=='g'){
+            let window = self.window_mut(self.in_use);
+            window.show_gutter = !window.show_gutter;
+        }
+        else if (self.browsing() && key=='l'){
+            self.next_layout();
+        }
+        else if (self.browsing() && key=='t'){
+            self.next_theme();
+        }
+        else if (self.browsing() && key=='?'){
+            self.more_hints = !self.more_hints;
+            self.update_screen();
+        }
         else if (key=='e' && !self.editing){
             self.edit_file_setup();
         }
 
+        else if (self.editing && ((self.modifiers.ctrl && key=='g') || key=='\u{7}')){
+            // Ctrl+G, since a plain 'g' is text in the editor.
+            let window = self.window_mut(self.in_use);
+            window.show_gutter = !window.show_gutter;
+            window.render_editor();
+            self.update_screen();
+        }
         else if (self.editing){
-            self.edit_file_text(key);
+            self.edit_key(key);
         }
         self.wait_check();
         if (self.waiting){
@@ -1144,139 +2333,701 @@ impl Kernel {
     }
 
     pub fn edit_file_setup(&mut self){
-        if self.in_use == 1{
-            self.quad_f1.is_being_edited = true;
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f1.current_highlighted];
-            self.quad_f1.current_file = u8_name;
-            self.editing = true;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            
-            let mut window_content_buffer = ['\0'; (WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.quad_f1.all_contents_u8 = content_buffer;
-            self.quad_f1.find_u8_index();
-            self.filesystem.close(fd);
-            for i in 0..((WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)){
-                
-                window_content_buffer[i] = content_buffer[i] as char;
-            }
-            self.write_to_window(1, window_content_buffer)
-        }
-        else if self.in_use == 2{
-            self.quad_f2.is_being_edited = true;
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f2.current_highlighted];
-            self.quad_f1.current_file = u8_name;
-            self.editing = true;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            
-            let mut window_content_buffer = ['\0'; (WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.quad_f2.all_contents_u8 = content_buffer;
-            self.quad_f2.find_u8_index();
-            self.filesystem.close(fd);
-            for i in 0..((WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)){
-                
-                window_content_buffer[i] = content_buffer[i] as char;
-            }
-            self.write_to_window(2, window_content_buffer)
-        }
-        else if self.in_use == 3{
-            self.quad_f3.is_being_edited = true;
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f3.current_highlighted];
-            self.quad_f3.current_file = u8_name;
-            self.editing = true;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            let mut window_content_buffer = ['\0'; (WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.quad_f3.all_contents_u8 = content_buffer;
-            self.quad_f3.find_u8_index();
-            self.filesystem.close(fd);
-            for i in 0..((WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)){
-                window_content_buffer[i] = content_buffer[i] as char;
-            }
-            self.write_to_window(3, window_content_buffer)
-        }
-        else if self.in_use == 4{
-            self.quad_f4.is_being_edited = true;
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f4.current_highlighted];
-            self.quad_f4.current_file = u8_name;
-            self.editing = true;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            let mut window_content_buffer = ['\0'; (WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.quad_f4.all_contents_u8 = content_buffer;
-            self.quad_f4.find_u8_index();
-            self.filesystem.close(fd);
-            for i in 0..((WINDOW_WIDTH-2) * (WINDOW_HEIGHT-2)){
-                window_content_buffer[i] = content_buffer[i] as char;
-            }
-            self.write_to_window(4, window_content_buffer)
+        if self.in_use == 0{
+            return;
         }
+        if let Some(u8_name) = self.highlighted_file(false){
+            self.open_editor(self.in_use, u8_name);
+        }
+    }
+
+    pub fn open_editor(&mut self, window_num: usize, u8_name: [u8; MAX_FILENAME_BYTES]){
+        let str_name = core::str::from_utf8(&u8_name).unwrap();
+        let mut content_buffer = vec![0u8; MAX_FILE_BYTES];
+        if self.read_file(str_name, &mut content_buffer).is_none(){
+            return;
+        }
+        let source = self.editor_for(u8_name, window_num);
+        self.editing = true;
+        let window = self.window_mut(window_num);
+        window.is_being_edited = true;
+        window.current_file = u8_name;
+        window.load_for_editing(&content_buffer);
+        if let Some(source) = source{
+            let window = self.window_mut(window_num);
+            window.read_only = true;
+            window.viewing = source;
+            self.sync_viewers();
+            let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+            self.show_message(format_args!("{} is open in F{}, viewing read-only", name, source));
+        }
+        else if self.attributes.is_locked(u8_name){
+            self.window_mut(window_num).read_only = true;
+            let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+            self.show_message(format_args!("{} is read-only", name));
+        }
+        self.update_screen();
+    }
+
+    // The window (other than except) holding a writable editor on this file.
+    pub fn editor_for(&self, u8_name: [u8; MAX_FILENAME_BYTES], except: usize) -> Option<usize>{
+        (1..5).find(|n| {
+            let window = self.window(*n);
+            *n != except && window.editor_open && !window.read_only && window.current_file == u8_name
+        })
+    }
+
+    // Read-only views follow the live buffer of the editor they were opened against.
+    pub fn sync_viewers(&mut self){
+        for n in 1..5{
+            let source = self.window(n).viewing;
+            if source == 0 || !self.window(n).editor_open{
+                continue;
+            }
+            let source = self.window(source);
+            if !source.editor_open || source.read_only || source.current_file != self.window(n).current_file{
+                continue;
+            }
+            let (buffer, len) = (source.all_contents_u8.clone(), source.file_len);
+            let window = self.window_mut(n);
+            if window.file_len == len && window.all_contents_u8[..len] == buffer[..len]{
+                continue;
+            }
+            window.all_contents_u8 = buffer;
+            window.file_len = len;
+            window.cursor = window.cursor.min(len);
+            window.render_editor();
+        }
+    }
+
+    pub fn edit_key(&mut self, key: char){
+        let window = self.window_mut(self.in_use);
+        if window.read_only{
+            return;
+        }
+        if let Some((start, end)) = window.selection_range(){
+            window.delete_range(start, end);
+            if key == '\u{8}'{
+                window.render_editor();
+                self.update_screen();
+                return;
+            }
+        }
+        window.selection_anchor = None;
+        match key{
+            '\u{8}' => window.edit_backspace(),
+            '\n' => window.edit_newline(),
+            '\t' => window.edit_tab(),
+            '}' => window.edit_close_brace(),
+            _ => {
+                if is_drawable(key){
+                    window.edit_insert(key as u8);
+                }
+            }
+        }
+        window.render_editor();
+        self.update_screen();
     }
 
     pub fn run_file(&mut self){
+        if self.in_use == 0 || self.has_process(self.in_use){
+            return;
+        }
+        if let Some(u8_name) = self.highlighted_file(false){
+            self.start_program(self.in_use, u8_name);
+        }
+    }
 
-        if self.in_use == 1 && !self.bool_f1.0{
-            
-            self.quad_f1.is_being_edited = true;
-            self.quad_f1.reset_colors();
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f1.current_highlighted];
-            self.quad_f1.current_file = u8_name;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.filesystem.close(fd);
-            let program_text = core::str::from_utf8(&content_buffer[0..contents]).unwrap();
-            self.int_f1 = Interpreter::new(program_text);
-            
-            self.bool_f1.0 = true;
-            let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            
-            self.write_to_window(1, empty);
-            self.process_ran +=1;
-            
+    // Only F1-F3 have an interpreter, so F4 never counts as free.
+    pub fn has_process(&self, window_num: usize) -> bool{
+        match window_num{
+            1 => self.bool_f1.0,
+            2 => self.bool_f2.0,
+            3 => self.bool_f3.0,
+            _ => true,
         }
-        else if self.in_use == 2 && !self.bool_f2.0{
-            
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f2.current_highlighted];
-            self.quad_f2.current_file = u8_name;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.filesystem.close(fd);
-            let program_text = core::str::from_utf8(&content_buffer[0..contents]).unwrap();
-            self.int_f2 = Interpreter::new(program_text);
-            self.bool_f2.0 = true;
-            let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            self.write_to_window(2, empty);
-        }
-        else if self.in_use == 3 && !self.bool_f3.0{
-            
-            let u8_name =self.filesystem.list_directory().unwrap().1[self.quad_f3.current_highlighted];
-            self.quad_f3.current_file = u8_name;
-            let str_name = core::str::from_utf8(&u8_name).unwrap();
-            let fd = self.filesystem.open_read(str_name).unwrap();
-            let mut content_buffer = ['\0' as u8;MAX_FILE_BYTES];
-            let contents = self.filesystem.read(fd, &mut content_buffer).unwrap();
-            self.filesystem.close(fd);
-            let program_text = core::str::from_utf8(&content_buffer[0..contents]).unwrap();
-            self.int_f3 = Interpreter::new(program_text);
-            self.bool_f3.0 = true;
-            let empty = ['\0';(WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)];
-            self.write_to_window(3, empty);
+    }
+
+    pub fn start_program(&mut self, window_num: usize, u8_name: [u8; MAX_FILENAME_BYTES]){
+        let str_name = core::str::from_utf8(&u8_name).unwrap();
+        let mut content_buffer = vec![0u8; MAX_FILE_BYTES];
+        let contents = match self.read_file(str_name, &mut content_buffer){
+            Some(contents) => contents,
+            None => return,
+        };
+        let program_text = match core::str::from_utf8(&content_buffer[0..contents]){
+            Ok(program_text) => program_text,
+            Err(_) => {
+                self.show_error(format_args!("{} is not text", str_name.trim_end_matches('\0')));
+                return;
+            }
+        };
+        match window_num{
+            1 => {
+                self.int_f1 = boxed(|| Interpreter::new(program_text));
+                self.bool_f1.0 = true;
+            }
+            2 => {
+                self.int_f2 = boxed(|| Interpreter::new(program_text));
+                self.bool_f2.0 = true;
+            }
+            3 => {
+                self.int_f3 = boxed(|| Interpreter::new(program_text));
+                self.bool_f3.0 = true;
+            }
+            _ => return,
+        }
+        self.return_to_editor[window_num-1] = None;
+        let window = self.window_mut(window_num);
+        window.is_being_edited = true;
+        window.reset_colors();
+        window.current_file = u8_name;
+        let empty = ['\0'; MAX_CONTENT_CELLS];
+        self.write_to_window(window_num, empty);
+        self.process_ran +=1;
+    }
+
+    // Save the buffer being edited and run it, preferring the editor's own window.
+    pub fn run_from_editor(&mut self){
+        let editor = self.in_use;
+        let target = if !self.has_process(editor){
+            Some(editor)
+        }
+        else{
+            (1..4).find(|n| !self.has_process(*n))
+        };
+        let target = match target{
+            Some(target) => target,
+            None => {
+                self.show_error(format_args!("No free window to run in"));
+                return;
+            }
+        };
+        let window = self.window_mut(editor);
+        let u8_name = window.current_file;
+        let cursor = window.cursor;
+        if !self.shut_off_editing(){
+            return;
+        }
+        self.update_in_use(target);
+        self.start_program(target, u8_name);
+        self.return_to_editor[target-1] = Some((editor, cursor));
+    }
+
+    // Stop a process whose interpreter reported an error. If it was launched from the
+    // editor, reopen the file there with the cursor where it was; the error, with
+    // whatever position simple_interp puts in it, stays on the status bar.
+    pub fn program_error<E: core::fmt::Debug>(&mut self, window_num: usize, e: E){
+        match window_num{
+            1 => self.bool_f1 = (false, false),
+            2 => self.bool_f2 = (false, false),
+            _ => self.bool_f3 = (false, false),
+        }
+        self.running = false;
+        self.wait_check();
+        self.show_error(format_args!("F{} error: {:?}", window_num, e));
+        if let Some((editor, cursor)) = self.return_to_editor[window_num-1].take(){
+            if self.window(editor).editor_open{
+                return;
+            }
+            let u8_name = self.window_mut(window_num).current_file;
+            self.update_in_use(editor);
+            self.open_editor(editor, u8_name);
+            let window = self.window_mut(editor);
+            window.cursor = cursor.min(window.file_len);
+            window.render_editor();
+            self.update_screen();
         }
+    }
 
-     }
+    // True when the focused window is showing the file grid.
+    pub fn browsing(&self) -> bool{
+        !self.user_is_typing && !self.editing && self.in_use != 0 && !self.window(self.in_use).is_being_edited
+    }
+
+    // Redraw the file grid in every window that is showing it.
+    pub fn refresh_listings(&mut self){
+        for i in 1..5{
+            if !self.window(i).is_being_edited{
+                self.refresh_listing(i);
+            }
+        }
+    }
+
+    pub fn start_prompt(&mut self, prompt: &str, action: PromptAction){
+        self.prompt_window = self.in_use;
+        self.set_prompt(prompt);
+        self.prompt_action = action;
+        self.update_in_use(0);
+        self.user_is_typing = true;
+    }
+
+    fn set_prompt(&mut self, prompt: &str){
+        self.user_input = ['\0'; MAX_USER_INPUT_BYTES];
+        for (i,c) in prompt.chars().enumerate(){
+            self.user_input[i] = c;
+        }
+        self.prompt_len = prompt.len();
+        self.current_user_input_index = prompt.len();
+        self.update_screen();
+    }
+
+    // Input too long for the top row scrolls left so the end being typed stays in view.
+    fn prompt_scroll(&self) -> usize{
+        (self.current_user_input_index + 1).saturating_sub(PROMPT_WIDTH)
+    }
+
+    pub fn reset_prompt(&mut self){
+        self.set_prompt(FILENAME_PROMPT);
+        self.prompt_action = PromptAction::Create;
+    }
+
+    // Names typed into the prompt may be paths relative to the directory of the
+    // window the prompt was opened from.
+    pub fn submit_prompt(&mut self){
+        let len = self.current_user_input_index - self.prompt_len;
+        let mut typed = [0 as u8; MAX_PATH_BYTES];
+        for (i,c) in self.user_input[self.prompt_len..self.current_user_input_index].iter().enumerate(){
+            typed[i] = *c as u8;
+        }
+        let text = core::str::from_utf8(&typed[..len]).unwrap().trim();
+        let base = if self.prompt_window == 0 { ROOT } else { self.window(self.prompt_window).current_dir };
+        let (dir, leaf) = match self.directories.resolve(base, text){
+            Some(resolved) => resolved,
+            None => {
+                self.show_error(format_args!("No such directory: {}", text));
+                return;
+            }
+        };
+        let u8_name = match padded_name(leaf){
+            Some(u8_name) if valid_filename(leaf) => u8_name,
+            _ => {
+                self.show_error(format_args!("Names are 1-{} of a-z 0-9 _ - .", MAX_FILENAME_BYTES));
+                return;
+            }
+        };
+        let action = self.prompt_action;
+        match action{
+            PromptAction::Open | PromptAction::Run => {
+                if !self.file_exists(u8_name) || self.directories.parent_of_file(u8_name) != dir{
+                    self.show_error(format_args!("{} not found", text));
+                    return;
+                }
+            }
+            PromptAction::MakeDirectory => {
+                if self.directories.find_child(dir, u8_name).is_some(){
+                    self.show_error(format_args!("{} already exists", leaf));
+                    return;
+                }
+            }
+            _ => {
+                // Renaming to the same name in another directory just moves the file.
+                if self.file_exists(u8_name) && action != PromptAction::Rename(u8_name){
+                    self.show_error(format_args!("{} already exists", leaf));
+                    return;
+                }
+                let count = self.list_files().0;
+                if count >= MAX_FILES_STORED{
+                    self.show_error(format_args!("Disk full: {} files", count));
+                    return;
+                }
+            }
+        }
+        match action{
+            PromptAction::Create => {
+                match self.filesystem.open_create(leaf){
+                    Ok(fd) => {
+                        let _ = self.filesystem.close(fd);
+                    }
+                    Err(e) => {
+                        self.show_error(format_args!("Cannot create {}: {:?}", leaf, e));
+                        return;
+                    }
+                }
+                if !self.directories.set_file_parent(u8_name, dir){
+                    self.remove_file(u8_name);
+                    self.show_error(format_args!("No room in the directory table for {}", leaf));
+                    return;
+                }
+                self.touch(u8_name);
+            }
+            PromptAction::Copy(source) => {
+                if let Err(e) = self.copy_file(source, leaf){
+                    self.show_error(format_args!("Cannot copy to {}: {:?}", leaf, e));
+                    return;
+                }
+                if !self.directories.set_file_parent(u8_name, dir){
+                    self.remove_file(u8_name);
+                    self.show_error(format_args!("No room in the directory table for {}", leaf));
+                    return;
+                }
+                self.touch(u8_name);
+            }
+            PromptAction::Rename(source) => {
+                // Only the directory entry changes; the inode and its blocks stay put.
+                // A path moves the file into the directory it names.
+                let parent = self.directories.parent_of_file(source);
+                if source == u8_name && parent == dir{
+                    self.show_error(format_args!("{} is already there", leaf));
+                    return;
+                }
+                if source != u8_name{
+                    if let Err(e) = self.filesystem.rename(core::str::from_utf8(&source).unwrap(), leaf){
+                        self.show_error(format_args!("Cannot rename to {}: {:?}", leaf, e));
+                        return;
+                    }
+                }
+                self.directories.forget_file(source);
+                if !self.directories.set_file_parent(u8_name, dir){
+                    // Put the old name back; the slot it frees is the one it had.
+                    if source != u8_name{
+                        let _ = self.filesystem.rename(leaf, core::str::from_utf8(&source).unwrap());
+                    }
+                    self.directories.set_file_parent(source, parent);
+                    self.show_error(format_args!("No room in the directory table for {}", leaf));
+                    return;
+                }
+                for entry in self.modified.iter_mut(){
+                    if entry.0 == source{
+                        entry.0 = u8_name;
+                    }
+                }
+                for n in 1..NUM_WINDOWS+1{
+                    let window = self.window_mut(n);
+                    if window.current_file == source{
+                        window.current_file = u8_name;
+                    }
+                }
+            }
+            PromptAction::MakeDirectory => {
+                if self.directories.create(dir, u8_name).is_none(){
+                    self.show_error(format_args!("No room for another directory"));
+                    return;
+                }
+            }
+            PromptAction::Open | PromptAction::Run => {}
+        }
+        self.save_tree();
+        self.reset_prompt();
+        if action != PromptAction::Create{
+            self.user_is_typing = false;
+            self.update_in_use(self.prompt_window);
+        }
+        if action == PromptAction::Open{
+            self.open_editor(self.prompt_window, u8_name);
+        }
+        else if action == PromptAction::Run{
+            if self.has_process(self.prompt_window){
+                self.show_error(format_args!("F{} cannot run another program", self.prompt_window));
+            }
+            else{
+                self.start_program(self.prompt_window, u8_name);
+            }
+        }
+        self.refresh_listings();
+    }
+
+    pub fn highlighted_entry(&mut self) -> Option<Entry>{
+        let window = self.window(self.in_use);
+        let (count, highlighted) = (window.file_count, window.current_highlighted);
+        if highlighted >= count{
+            self.show_error(format_args!("No file selected"));
+            return None;
+        }
+        Some(self.window(self.in_use).view[highlighted])
+    }
+
+    // The highlighted file's name; files being edited or run are refused when that matters.
+    pub fn highlighted_file(&mut self, refuse_in_use: bool) -> Option<[u8; MAX_FILENAME_BYTES]>{
+        let u8_name = match self.highlighted_entry()?{
+            Entry::File(name) => name,
+            _ => {
+                self.show_error(format_args!("Not a file"));
+                return None;
+            }
+        };
+        if refuse_in_use && self.file_in_use(u8_name){
+            let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+            self.show_error(format_args!("{} is being edited or run", name));
+            return None;
+        }
+        Some(u8_name)
+    }
+
+    pub fn file_exists(&mut self, u8_name: [u8; MAX_FILENAME_BYTES]) -> bool{
+        let (count, names) = self.list_files();
+        names[..count].contains(&u8_name)
+    }
+
+    pub fn copy_file(&mut self, source: [u8; MAX_FILENAME_BYTES], destination: &str) -> FileSystemResult<()>{
+        let mut content_buffer = vec![0u8; MAX_FILE_BYTES];
+        let fd = self.filesystem.open_read(core::str::from_utf8(&source).unwrap())?;
+        let len = self.filesystem.read(fd, &mut content_buffer);
+        let _ = self.filesystem.close(fd);
+        let len = len?;
+        let fd = self.filesystem.open_create(destination)?;
+        let written = self.filesystem.write(fd, &content_buffer[..len]);
+        let _ = self.filesystem.close(fd);
+        written
+    }
+
+    pub fn file_in_use(&self, u8_name: [u8; MAX_FILENAME_BYTES]) -> bool{
+        (1..5).any(|n| {
+            let window = self.window(n);
+            let running = n < 4 && self.has_process(n);
+            window.current_file == u8_name && (window.editor_open || running)
+        })
+    }
+
+    pub fn delete_highlighted(&mut self){
+        if let Some(Entry::Directory(dir)) = self.highlighted_entry(){
+            self.remove_directory(dir);
+            return;
+        }
+        let u8_name = match self.highlighted_file(true){
+            Some(u8_name) => u8_name,
+            None => return,
+        };
+        if self.refuse_locked(u8_name){
+            return;
+        }
+        let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+        self.pending_delete = Some(u8_name);
+        self.show_message(format_args!("Delete {}? (y/n)", name));
+    }
+
+    pub fn confirm_delete(&mut self, key: char){
+        if let Some(u8_name) = self.pending_delete.take(){
+            let name = core::str::from_utf8(&u8_name).unwrap().trim_end_matches('\0');
+            if key == 'y' || key == 'Y'{
+                if self.remove_file(u8_name){
+                    self.save_tree();
+                    self.refresh_listings();
+                    self.show_message(format_args!("Deleted {}", name));
+                }
+            }
+        }
+    }
+
+    // Unlink the file, freeing its inode and blocks; false after reporting an error.
+    pub fn remove_file(&mut self, u8_name: [u8; MAX_FILENAME_BYTES]) -> bool{
+        let name = core::str::from_utf8(&u8_name).unwrap();
+        if let Err(e) = self.filesystem.unlink(name){
+            self.show_error(format_args!("Cannot delete {}: {:?}", name.trim_end_matches('\0'), e));
+            return false;
+        }
+        self.directories.forget_file(u8_name);
+        self.attributes.forget_file(u8_name);
+        for entry in self.modified.iter_mut(){
+            if entry.0 == u8_name{
+                *entry = ([0; MAX_FILENAME_BYTES], 0);
+            }
+        }
+        true
+    }
+
+    // Shift+Up/Down starts a row selection in a window without an editor, as F8 does.
+    fn shift_line_selection(&mut self){
+        if self.modifiers.shift && !self.editing && self.in_use != 0 && !self.line_selecting(){
+            self.window_mut(self.in_use).toggle_selection();
+        }
+    }
+
+    // The browser's Ctrl+letter command for a key, as the letter; plain letters
+    // go to the filter. pc_keyboard may pass Ctrl+letter on as the letter itself or
+    // as its control code, and the codes Tab, Enter and Backspace share are left out,
+    // so Ctrl+I, Ctrl+J and Ctrl+H can't be browser commands.
+    fn browser_command(&self, key: char) -> Option<char>{
+        if self.modifiers.ctrl && key.is_ascii_alphabetic(){
+            Some(key.to_ascii_lowercase())
+        }
+        else if ('\u{1}'..='\u{1a}').contains(&key) && !"\t\n\u{8}".contains(key){
+            Some((key as u8 - 1 + b'a') as char)
+        }
+        else{
+            None
+        }
+    }
+
+    // Called from the cpu loop so shift and ctrl are current when a key arrives.
+    pub fn poll_modifiers(&mut self){
+        self.modifiers.poll();
+    }
+
+    pub fn line_selecting(&mut self) -> bool{
+        self.in_use != 0 && self.window_mut(self.in_use).line_selection.is_some()
+    }
+
+    pub fn awaiting_input(&self, window_num: usize) -> bool{
+        match window_num{
+            1 => self.bool_f1.1,
+            2 => self.bool_f2.1,
+            3 => self.bool_f3.1,
+            _ => false,
+        }
+    }
+
+    // Copy the focused window's selection into the kernel clipboard. Cutting only
+    // removes text from an editor; output windows are copied from.
+    pub fn copy_selection(&mut self, cut: bool){
+        let window = match self.in_use{
+            0 => return,
+            1 => &mut self.quad_f1,
+            2 => &mut self.quad_f2,
+            3 => &mut self.quad_f3,
+            _ => &mut self.quad_f4,
+        };
+        if window.editor_open{
+            if let Some((start, end)) = window.selection_range(){
+                self.clipboard[..end-start].copy_from_slice(&window.all_contents_u8[start..end]);
+                self.clipboard_len = end-start;
+                if cut && !window.read_only{
+                    window.delete_range(start, end);
+                }
+                else{
+                    window.selection_anchor = None;
+                }
+                window.render_editor();
+            }
+        }
+        else if window.line_selection.is_some(){
+            self.clipboard_len = window.copy_lines(&mut self.clipboard[..]);
+            window.clear_selection();
+        }
+        let copied = self.clipboard_len;
+        self.show_message(format_args!("Clipboard: {} bytes", copied));
+    }
+
+    // Paste into the editor at the cursor, or into the input line of a waiting program.
+    pub fn paste_clipboard(&mut self){
+        if self.editing{
+            let window = match self.in_use{
+                1 => &mut self.quad_f1,
+                2 => &mut self.quad_f2,
+                3 => &mut self.quad_f3,
+                _ => &mut self.quad_f4,
+            };
+            if window.read_only{
+                return;
+            }
+            if let Some((start, end)) = window.selection_range(){
+                window.delete_range(start, end);
+            }
+            window.selection_anchor = None;
+            for b in &self.clipboard[..self.clipboard_len]{
+                window.edit_insert(*b);
+            }
+            window.render_editor();
+        }
+        else if self.awaiting_input(self.in_use){
+            for i in 0..self.clipboard_len{
+                let c = self.clipboard[i] as char;
+                if c == '\n'{
+                    break;
+                }
+                if is_drawable(c) && self.ri_index < self.run_input.len(){
+                    self.run_input[self.ri_index] = c;
+                    self.ri_index +=1;
+                    self.edit_file_text(c);
+                }
+            }
+        }
+        self.update_screen();
+    }
+
+    // Messages show in the status bar until the next key press.
+    pub fn show_message(&mut self, args: core::fmt::Arguments){
+        self.clear_message();
+        let mut writer = RowWriter{row: &mut self.status_message, len: 0};
+        let _ = writer.write_fmt(args);
+        self.update_screen();
+    }
+
+    pub fn show_error(&mut self, args: core::fmt::Arguments){
+        self.show_message(args);
+        self.status_is_error = true;
+        self.update_status_bar();
+    }
+
+    pub fn clear_message(&mut self){
+        self.status_message = ['\0'; BUFFER_WIDTH];
+        self.status_is_error = false;
+    }
+
+    // The focused window's mode and the keys that apply in it.
+    fn status_mode(&self) -> (&'static str, &'static str){
+        if self.pending_delete.is_some(){
+            return ("CONFIRM", "y delete  any other key keeps the file");
+        }
+        if self.user_is_typing{
+            return ("PROMPT", "Enter submit  Backspace erase  F1-F4 cancel");
+        }
+        if self.in_use == 0{
+            return ("", "F1-F4 focus a window  F5 new file  Shift+F12 layout");
+        }
+        let window = self.window(self.in_use);
+        if window.editor_open && window.read_only{
+            ("VIEW", "F6 close  F8 mark  F9 copy  F12 max")
+        }
+        else if window.editor_open{
+            ("EDIT", "F6 save  F7 run  F9 copy  F10 cut  F11 paste  F12 max  ^G lines")
+        }
+        else if self.in_use < 4 && self.awaiting_input(self.in_use){
+            ("INPUT", "Enter send  F11 paste  F6 stop")
+        }
+        else if self.in_use < 4 && self.has_process(self.in_use){
+            ("RUN", "F6 stop  F8 mark  F9 copy  F12 max")
+        }
+        else if window.is_being_edited{
+            ("OUTPUT", "F6 back to files  F8 mark  F9 copy  F12 max")
+        }
+        else if window.filter_len > 0{
+            ("FILTER", "type to narrow  Backspace erase  Esc clear  ^E edit ^R run ^D del")
+        }
+        else if self.more_hints{
+            ("BROWSE", "^O open ^X exec ^P lock ^S sort ^V info ^G lines ^L view ^T theme ?")
+        }
+        else{
+            ("BROWSE", "type to find ^E edit ^R run ^D del ^N ren ^C copy ^M dir ? more")
+        }
+    }
+
+    // The bottom row: the mode, then either the pending message or key hints.
+    pub fn update_status_bar(&mut self){
+        let row = BUFFER_HEIGHT-1;
+        let (mode, hints) = self.status_mode();
+        let mut bar = ['\0'; BUFFER_WIDTH];
+        let mut writer = RowWriter{row: &mut bar, len: 0};
+        if mode.is_empty(){
+            let _ = write!(writer, " ");
+        }
+        else{
+            let _ = write!(writer, " {} F{} ", mode, self.in_use);
+        }
+        let mode_len = writer.len;
+        let _ = write!(writer, " ");
+        if self.status_message[0] != '\0'{
+            for c in self.status_message.iter().take_while(|c| **c != '\0'){
+                let _ = writer.write_char(*c);
+            }
+        }
+        else{
+            let _ = write!(writer, "{}", hints);
+        }
+        let theme = self.theme();
+        let (foreground, background) = if self.status_is_error { theme.error } else { theme.status };
+        for j in 0..BUFFER_WIDTH{
+            self.screen[row][j] = bar[j];
+            if j < mode_len{
+                self.foreground[row][j] = theme.mode.0;
+                self.background[row][j] = theme.mode.1;
+            }
+            else{
+                self.foreground[row][j] = foreground;
+                self.background[row][j] = background;
+            }
+        }
+    }
 
      pub fn run_one_instruction(&mut self) {
         
@@ -1289,19 +3040,15 @@ impl Kernel {
                     self.ri_index = 0;
                     self.input_flag1 = false;
                 }
-                self.quad_f1.reset_colors();
-                self.draw();
-                let mut output = KernelOutput::new(self.quad_f1, 1);
+                let mut output = KernelOutput::new(&mut self.quad_f1, 1);
                 
                 
                 
                 
                 let result: TickResult<()> = self.int_f1.tick(&mut output);
-                self.quad_f1 = output.window;
-                self.quad_f1.update_contents(self.quad_f1.contents);
                 // println!("{:?}",output.window.contents);
                 // panic!();
-                self.draw(); //maybe just update screen
+                self.request_render();
                 //println!()
                 self.num_ticks[self.turn_index] +=1;
                 match result{
@@ -1313,7 +3060,7 @@ impl Kernel {
                             self.edit_file_text(i);
                         }
                         self.in_use = temp_in_use;
-                        self.draw();
+                        self.request_render();
                         self.bool_f1 = (false, false);
                         self.running = false;
                     },
@@ -1322,8 +3069,7 @@ impl Kernel {
                         self.bool_f1.1 = true;
                     },
                     TickResult::Err(e) => {
-                        println!("{:?}", e);
-                        panic!();
+                        self.program_error(1, e);
                     },
                 }
             }
@@ -1340,19 +3086,15 @@ impl Kernel {
                     self.ri_index = 0;
                     self.input_flag2 = false;
                 }
-                self.quad_f2.reset_colors();
-                self.draw();
-                let mut output = KernelOutput::new(self.quad_f2, 2);
+                let mut output = KernelOutput::new(&mut self.quad_f2, 2);
                 
                 
                 
                 
                 let result: TickResult<()> = self.int_f2.tick(&mut output);
-                self.quad_f2 = output.window;
-                self.quad_f2.update_contents(self.quad_f2.contents);
                 // println!("{:?}",output.window.contents);
                 // panic!();
-                self.draw(); //maybe just update screen
+                self.request_render();
                 self.num_ticks[self.turn_index] +=1;
                 match result{
                     TickResult::Ok(_) => {},
@@ -1363,7 +3105,7 @@ impl Kernel {
                             self.edit_file_text(i);
                         }
                         self.in_use = temp_in_use;
-                        self.draw();
+                        self.request_render();
                         self.bool_f2 = (false, false);
                         self.running = false;
                     },
@@ -1372,8 +3114,7 @@ impl Kernel {
                         self.bool_f2.1 = true;
                     },
                     TickResult::Err(e) => {
-                        println!("{:?}", e);
-                        panic!();
+                        self.program_error(2, e);
                     },
                 }
             }
@@ -1390,19 +3131,15 @@ impl Kernel {
                     self.ri_index = 0;
                     self.input_flag3 = false;
                 }
-                self.quad_f3.reset_colors();
-                self.draw();
-                let mut output = KernelOutput::new(self.quad_f3, 3);
+                let mut output = KernelOutput::new(&mut self.quad_f3, 3);
                 
                 
                 
                 
                 let result: TickResult<()> = self.int_f3.tick(&mut output);
-                self.quad_f3 = output.window;
-                self.quad_f3.update_contents(self.quad_f3.contents);
                 // println!("{:?}",output.window.contents);
                 // panic!();
-                self.draw(); //maybe just update screen
+                self.request_render();
                 self.num_ticks[self.turn_index] +=1;
                 match result{
                     TickResult::Ok(_) => {},
@@ -1413,7 +3150,7 @@ impl Kernel {
                             self.edit_file_text(i);
                         }
                         self.in_use = temp_in_use;
-                        self.draw();
+                        self.request_render();
                         self.bool_f3 = (false, false);
                         self.running = false;
                     },
@@ -1422,8 +3159,7 @@ impl Kernel {
                         self.bool_f3.1 = true;
                     },
                     TickResult::Err(e) => {
-                        println!("{:?}", e);
-                        panic!();
+                        self.program_error(3, e);
                     },
                 }
             }
@@ -1440,67 +3176,146 @@ impl Kernel {
         self.quad_f4.update_borders();
 
     }
+    // Where a window sits on screen as (top row, left column, width, height), or None
+    // when the layout has no slot for it.
+    fn window_rect(&self, window_num: usize) -> Option<(usize, usize, usize, usize)>{
+        let slot = self.slots.iter().position(|n| *n == window_num)?;
+        if slot < self.layout.slots(){
+            Some(self.layout.rect(slot))
+        }
+        else{
+            None
+        }
+    }
+
     pub fn update_screen(&mut self){
         for i in 0..BUFFER_HEIGHT-1{
             for j in 0..BUFFER_WIDTH{
-                if i == 0 && j < BUFFER_WIDTH-TASK_MANAGER_WIDTH{
-                    if j < MAX_USER_INPUT_BYTES{
-                        self.screen[i][j] = self.user_input[j];
+                if i == 0 && j < PROMPT_WIDTH{
+                    if j + self.prompt_scroll() < MAX_USER_INPUT_BYTES{
+                        self.screen[i][j] = self.user_input[j + self.prompt_scroll()];
                     }
                     else{
-                        self.screen[i][j] = '\0';
+                        self.screen[i][j] = self.top_row[j];
                     }
-                    
+                    (self.foreground[i][j], self.background[i][j]) = self.theme().text;
                 }
                 else if j >= BUFFER_WIDTH-TASK_MANAGER_WIDTH{
                     self.screen[i][j] = self.task_manager[i][j-(BUFFER_WIDTH-TASK_MANAGER_WIDTH)];
+                    (self.foreground[i][j], self.background[i][j]) = self.theme().task_manager;
                 }
                 else{
-                    if (i <= WINDOW_HEIGHT && j < WINDOW_WIDTH){
-                        
-                        self.screen[i][j] = self.quad_f1.vga[(i-1)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.foreground[i][j] = self.quad_f1.foreground[(i-1)%WINDOW_HEIGHT][j%WINDOWS_WIDTH];
-                        self.background[i][j] = self.quad_f1.background[(i-1)%WINDOW_HEIGHT][j%WINDOWS_WIDTH];
-                        
-                    }
-                    if (i <= WINDOW_HEIGHT && j >= WINDOW_WIDTH){
-                        self.screen[i][j-1] = self.quad_f2.vga[(i-1)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.foreground[i][j-1] = self.quad_f2.foreground[(i-1)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.background[i][j-1] = self.quad_f2.background[(i-1)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
+                    self.screen[i][j] = '\0';
+                    (self.foreground[i][j], self.background[i][j]) = self.theme().text;
+                }
 
-                        if self.in_use==1 && j%WINDOW_WIDTH==0 && self.screen[i][j-1] == '.' {
-                            self.screen[i][j-1] = '*';
-                        }
-                    }
-                    if (i >= WINDOW_HEIGHT && j < WINDOW_WIDTH){
-                        self.screen[i][j] = self.quad_f3.vga[i%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.foreground[i][j] = self.quad_f3.foreground[i%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.background[i][j] = self.quad_f3.background[i%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        if self.in_use==1 && i%WINDOW_HEIGHT==0 && self.screen[i][j] == '.' {
-                            self.screen[i][j] = '*';
-                        }
-                        //add stuff for * if top selected
+            }
+        }
+        // Later windows are drawn over the borders they share with earlier ones.
+        for n in 1..NUM_WINDOWS+1{
+            if let Some((top, left, width, height)) = self.window_rect(n){
+                for i in 0..height{
+                    for j in 0..width{
+                        let window = self.window(n);
+                        let cell = (window.vga[i][j], window.foreground[i][j], window.background[i][j]);
+                        self.screen[top+i][left+j] = cell.0;
+                        self.foreground[top+i][left+j] = cell.1;
+                        self.background[top+i][left+j] = cell.2;
                     }
-                    if (i >= WINDOW_HEIGHT && j >= WINDOW_WIDTH){
-                        self.screen[i][j-1] = self.quad_f4.vga[(i)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.foreground[i][j-1] = self.quad_f4.foreground[(i)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        self.background[i][j-1] = self.quad_f4.background[(i)%WINDOW_HEIGHT][j%WINDOW_WIDTH];
-                        if self.in_use==2 && i%WINDOW_HEIGHT==0 && self.screen[i][j-1] == '.' {
-                            self.screen[i][j-1] = '*';
-                        }
-                        if self.in_use==3 && j%WINDOW_WIDTH==0 && self.screen[i][j-1] == '.' {
-                            self.screen[i][j-1] = '*';
-                        }
-                        if self.in_use==1 && i%WINDOW_HEIGHT==0 && j%WINDOW_WIDTH == 0 && self.screen[i][j-1] == '.' {
-                            self.screen[i][j-1] = '*';
+                }
+            }
+        }
+        // The focused window's border shows through where a neighbour's plain border covers it.
+        if self.in_use != 0{
+            if let Some((top, left, width, height)) = self.window_rect(self.in_use){
+                for i in 0..height{
+                    for j in 0..width{
+                        if self.window(self.in_use).vga[i][j] == '*' && self.screen[top+i][left+j] == '.'{
+                            self.screen[top+i][left+j] = '*';
+                            self.foreground[top+i][left+j] = self.theme().focused_border;
                         }
-                        
-                        //add stuff for * if top selected
                     }
                 }
+            }
+        }
+        self.update_status_bar();
+    }
 
+    // F12: the focused window fills the area below the prompt until F12 is pressed again.
+    pub fn toggle_maximized(&mut self){
+        if self.layout == Layout::Full{
+            self.set_layout(self.previous_layout);
+        }
+        else if self.in_use != 0{
+            self.set_layout(Layout::Full);
+        }
+    }
+
+    pub fn theme(&self) -> &'static Theme{
+        &THEMES[self.theme]
+    }
+
+    // Ctrl+T in the browser: switch every window to the next theme.
+    pub fn next_theme(&mut self){
+        self.theme = (self.theme + 1) % THEMES.len();
+        for n in 1..NUM_WINDOWS+1{
+            self.window_mut(n).theme = &THEMES[self.theme];
+        }
+        self.show_message(format_args!("Theme: {}", self.theme().name));
+    }
+
+    // Ctrl+L in the browser, or shift+F12 anywhere: step through the layouts.
+    pub fn next_layout(&mut self){
+        let layout = self.layout.next();
+        self.set_layout(layout);
+        self.show_message(format_args!("Layout: {}", layout.label()));
+    }
+
+    // previous_layout remembers the last layout with more than one window for F12.
+    pub fn set_layout(&mut self, layout: Layout){
+        self.layout = layout;
+        if layout != Layout::Full{
+            self.previous_layout = layout;
+        }
+        if self.in_use != 0{
+            self.show_window(self.in_use, 0);
+        }
+        self.arrange_windows();
+    }
+
+    // Give a hidden window a slot on screen, taking it from a window other than keep
+    // (the one that just had the focus) when the layout has room for both.
+    pub fn show_window(&mut self, window_num: usize, keep: usize){
+        let shown = self.layout.slots();
+        let slot = self.slots.iter().position(|n| *n == window_num).unwrap();
+        if slot < shown{
+            return;
+        }
+        let replaced = (0..shown).rev().find(|s| self.slots[*s] != keep).unwrap_or(0);
+        self.slots.swap(slot, replaced);
+        self.arrange_windows();
+    }
+
+    // Size each shown window to its slot. Hidden windows keep their last size, and
+    // their programs keep running in it.
+    pub fn arrange_windows(&mut self){
+        for n in 1..NUM_WINDOWS+1{
+            let (width, height) = match self.window_rect(n){
+                Some((_, _, width, height)) => (width, height),
+                None => continue,
+            };
+            let window = self.window_mut(n);
+            if window.width == width && window.height == height{
+                continue;
+            }
+            window.resize(width, height);
+            window.reset_colors();
+            window.update_borders();
+            if !window.is_being_edited{
+                self.refresh_listing(n);
             }
         }
+        self.update_screen();
     }
 
     pub fn update_colors(&mut self){
@@ -1511,17 +3326,77 @@ impl Kernel {
 
     }
     pub fn draw(&mut self) {
+        self.sync_viewers();
 
         self.update_colors();
         self.update_borders();
         self.update_screen();
-        
+        self.present();
+        self.place_cursor();
+        self.needs_render = false;
+    }
+
+    // Screen cell where the next typed character lands: the prompt, the focused
+    // editor, or a process waiting for input. None when nothing takes text.
+    fn insertion_point(&self) -> Option<(usize, usize)>{
+        if self.user_is_typing{
+            return Some((0, self.current_user_input_index - self.prompt_scroll()));
+        }
+        if self.in_use == 0 || self.pending_delete.is_some(){
+            return None;
+        }
+        let (top, left, _, _) = self.window_rect(self.in_use)?;
+        let window = self.window(self.in_use);
+        if window.editor_open && !window.read_only{
+            let (row, col) = window.visible_cell(window.cursor)?;
+            Some((top+1+row, left+1+col))
+        }
+        else if self.awaiting_input(self.in_use){
+            let (cols, rows) = window.content_size();
+            let index = window.current_contents_index.min(cols*rows-1);
+            Some((top+1+index/cols, left+1+index%cols))
+        }
+        else{
+            None
+        }
+    }
+
+    // Move the blinking hardware cursor only when the insertion point changes.
+    // hardware_cursor starts off screen so the first frame always sets it.
+    fn place_cursor(&mut self){
+        let point = self.insertion_point();
+        if point == self.hardware_cursor{
+            return;
+        }
+        match point{
+            Some((row, col)) => cursor::show(row, col),
+            None => cursor::hide(),
+        }
+        self.hardware_cursor = point;
+    }
+
+    // Plot only the cells that differ from the last frame sent to the VGA buffer.
+    fn present(&mut self){
         for i in 0..BUFFER_HEIGHT{
             for j in 0..BUFFER_WIDTH{
-                plot(self.screen[i][j], j, i, ColorCode::new(self.foreground[i][j], self.background[i][j]));
+                let cell = (self.screen[i][j], self.foreground[i][j], self.background[i][j]);
+                if self.presented[i][j] != cell{
+                    plot(cell.0, j, i, ColorCode::new(cell.1, cell.2));
+                    self.presented[i][j] = cell;
+                }
             }
         }
-        
+    }
+
+    // Mark the screen stale; the main loop redraws it on its next render.
+    pub fn request_render(&mut self){
+        self.needs_render = true;
+    }
+
+    pub fn render(&mut self){
+        if self.needs_render{
+            self.draw();
+        }
     }
 
     pub fn tick_numbers(&mut self, spot: usize) -> (char, char, char, char){
@@ -1567,7 +3442,30 @@ impl Kernel {
 
 
     }
+    // Instructions each window ran over the last second, from the growth of num_ticks.
+    fn update_rates(&mut self){
+        self.rate_ticks +=1;
+        if self.rate_ticks < TICKS_PER_SECOND{
+            return;
+        }
+        self.rate_ticks = 0;
+        for i in 0..4{
+            self.rates[i] = self.num_ticks[i].saturating_sub(self.rate_start[i]);
+            self.rate_start[i] = self.num_ticks[i];
+        }
+        self.task_manager[RATE_ROW] = ['\0'; TASK_MANAGER_WIDTH];
+        let _ = write!(RowWriter{row: &mut self.task_manager[RATE_ROW], len: 0}, "Instr/sec");
+        for i in 0..3{
+            let row = &mut self.task_manager[RATE_ROW+1+i];
+            *row = ['\0'; TASK_MANAGER_WIDTH];
+            let _ = write!(RowWriter{row, len: 0}, "F{} {:>7}", i+1, self.rates[i]);
+        }
+    }
+
+    // Runs every timer tick; the screen is only redrawn when the text changes.
     pub fn draw_proc_status(&mut self) {
+        let before = self.task_manager;
+        self.update_rates();
         self.task_manager[0][0] = 'F';
         self.task_manager[0][1] = '1';
         self.task_manager[2][0] = 'F';
@@ -1603,33 +3501,35 @@ impl Kernel {
         self.task_manager[7][1] = f4_ticks.1;
         self.task_manager[7][2] = f4_ticks.2;
         self.task_manager[7][3] = f4_ticks.3;
-        self.update_screen()
+        if self.task_manager != before{
+            self.request_render();
+        }
     }
 
     
 }
 
 
-pub struct KernelOutput{
-    window: Window,
+// Writes program output straight into the kernel's window while it ticks.
+pub struct KernelOutput<'a>{
+    window: &'a mut Window,
     which_one: usize,
     
 }
 
-impl KernelOutput {
-    fn new(window: Window, which_one: usize) -> Self{
-        let mut window = window;
-        let mut which_one = which_one;
-        Self { window: window, which_one: which_one,  }
+impl<'a> KernelOutput<'a> {
+    fn new(window: &'a mut Window, which_one: usize) -> Self{
+        Self { window, which_one }
     }
 
 
-    pub fn write_to_window(&mut self, window_num: usize, contents: [char; (WINDOW_WIDTH-2)*(WINDOW_HEIGHT-2)]){
+    pub fn write_to_window(&mut self, window_num: usize, contents: [char; MAX_CONTENT_CELLS]){
 
 
         let mut char_count = 0;
         let mut row_count = 0;
-        let mut c = [['\0';WINDOW_WIDTH-2];WINDOW_HEIGHT-2];
+        let (width, height) = (self.window.width, self.window.height);
+        let mut c = [['\0'; MAX_WINDOW_WIDTH-2]; MAX_WINDOW_HEIGHT-2];
         for i in contents{
             if i as char =='\n'{
                 
@@ -1638,11 +3538,11 @@ impl KernelOutput {
                 continue;
             }
             let mut val = 2;
-            if char_count >= WINDOW_WIDTH-val{
+            if char_count >= width-val{
                 char_count = 0;
                 row_count +=1;
             }
-            if row_count >=WINDOW_HEIGHT-2{
+            if row_count >=height-2{
                 break;
             }
             c[row_count][char_count] = i as char;
@@ -1661,7 +3561,7 @@ impl KernelOutput {
     
 }
 
-impl InterpreterOutput for KernelOutput {
+impl InterpreterOutput for KernelOutput<'_> {
     fn print(&mut self, chars: &[u8]) {
         // println!("{:?}",chars);
         // panic!();
@@ -1681,10 +3581,19 @@ impl InterpreterOutput for KernelOutput {
 
 
 
-fn text_color() -> ColorCode {
-    ColorCode::new(Color::White, Color::Black)
+struct RowWriter<'a>{
+    row: &'a mut [char],
+    len: usize,
 }
 
-fn highlight_color() -> ColorCode {
-    ColorCode::new(Color::Black, Color::White)
-}
\ No newline at end of file
+impl Write for RowWriter<'_>{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result{
+        for c in s.chars(){
+            if self.len < self.row.len(){
+                self.row[self.len] = c;
+                self.len +=1;
+            }
+        }
+        Ok(())
+    }
+}
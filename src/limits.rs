@@ -0,0 +1,14 @@
+// The FileSystem's sizes, shared by the kernel, build.rs and the diskimg tool so
+// the programs packed at build time and the images made on the host always fit
+// the disk the kernel mounts.
+
+pub const MAX_OPEN: usize = 16;
+pub const BLOCK_SIZE: usize = 256;
+pub const NUM_BLOCKS: usize = 255;
+pub const MAX_FILE_BLOCKS: usize = 64;
+pub const MAX_FILE_BYTES: usize = MAX_FILE_BLOCKS * BLOCK_SIZE;
+pub const MAX_FILES_STORED: usize = 30;
+pub const MAX_FILENAME_BYTES: usize = 10;
+
+// File slots taken by the kernel's hidden directory and attribute tables.
+pub const RESERVED_FILES: usize = 2;
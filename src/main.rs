@@ -1,14 +1,24 @@
 #![no_std]
 #![no_main]
 
+use bootloader::{entry_point, BootInfo};
 use pc_keyboard::DecodedKey;
 use pluggable_interrupt_os::HandlerTable;
+use pluggable_interrupt_os::println;
 use pluggable_interrupt_os::vga_buffer::clear_screen;
 use swim_template::Kernel;
 use crossbeam::atomic::AtomicCell;
+use x86_64::instructions::hlt;
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
+entry_point!(kernel_main);
+
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    if let Err(e) = swim_template::allocator::init_heap(boot_info) {
+        println!("Cannot map the kernel heap: {:?}", e);
+        loop {
+            hlt();
+        }
+    }
     HandlerTable::new()
         .keyboard(key)
         .timer(tick)
@@ -20,14 +30,19 @@ pub extern "C" fn _start() -> ! {
 static LAST_KEY: AtomicCell<Option<DecodedKey>> = AtomicCell::new(None);
 static TICKS: AtomicCell<usize> = AtomicCell::new(0);
 
+// The timer runs at about 18.2 Hz, so this caps rendering near 9 frames per second.
+const TICKS_PER_FRAME: usize = 2;
+
 fn cpu_loop() -> ! {
     let mut kernel = Kernel::new();
     
     let mut last_tick = 0;
+    let mut last_frame = 0;
     kernel.make_initial_files();
     kernel.draw();
     
     loop {
+        kernel.poll_modifiers();
         if let Some(key) = LAST_KEY.load() {
             LAST_KEY.store(None);
             kernel.key(key);
@@ -36,6 +51,10 @@ fn cpu_loop() -> ! {
         if current_tick > last_tick {
             last_tick = current_tick;
             kernel.draw_proc_status();
+            if current_tick - last_frame >= TICKS_PER_FRAME {
+                last_frame = current_tick;
+                kernel.render();
+            }
         }
         kernel.run_one_instruction();
     }
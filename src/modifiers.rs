@@ -0,0 +1,53 @@
+// Modifier keys, read straight from the keyboard controller.
+//
+// pc_keyboard folds shift and ctrl into the keys it decodes and never reports them
+// on their own, so shift+arrow looks like a plain arrow by the time it reaches the
+// kernel. While the controller's output buffer is empty its data port still returns
+// the last scancode the keyboard handler took, so polling it from the cpu loop sees
+// each modifier press and release without consuming bytes meant for the handler.
+
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const OUTPUT_FULL: u8 = 0x01;
+
+const EXTENDED: u8 = 0xE0;
+const RELEASED: u8 = 0x80;
+const LEFT_SHIFT: u8 = 0x2A;
+const RIGHT_SHIFT: u8 = 0x36;
+const CTRL: u8 = 0x1D;
+
+#[derive(Clone, Copy, Default)]
+pub struct Modifiers{
+    pub shift: bool,
+    pub ctrl: bool,
+    last: u8,
+}
+
+impl Modifiers{
+    pub fn poll(&mut self){
+        let scancode = without_interrupts(|| unsafe {
+            if Port::<u8>::new(STATUS_PORT).read() & OUTPUT_FULL != 0{
+                // A byte is waiting for the handler; leave it alone.
+                return None;
+            }
+            Some(Port::<u8>::new(DATA_PORT).read())
+        });
+        if let Some(scancode) = scancode{
+            if scancode == self.last{
+                return;
+            }
+            let down = scancode & RELEASED == 0;
+            match scancode & !RELEASED{
+                // Shift codes after the extended prefix are the fake shifts some
+                // keyboards wrap around the arrow keys, not real presses.
+                LEFT_SHIFT | RIGHT_SHIFT if self.last != EXTENDED => self.shift = down,
+                CTRL => self.ctrl = down,
+                _ => (),
+            }
+            self.last = scancode;
+        }
+    }
+}
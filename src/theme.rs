@@ -0,0 +1,96 @@
+// Color themes for the windows, status bar and task manager.
+//
+// Color pairs are (foreground, background). The kernel holds one of THEMES and
+// hands it to every window, which paints its cells from it instead of fixed colors.
+
+use pluggable_interrupt_os::vga_buffer::Color;
+
+pub struct Theme{
+    pub name: &'static str,
+    pub text: (Color, Color),
+    pub highlight: (Color, Color),
+    pub focused_border: Color,
+    pub unfocused_border: Color,
+    pub selection: (Color, Color),
+    pub locked: Color,
+    pub error: (Color, Color),
+    pub status: (Color, Color),
+    pub mode: (Color, Color),
+    pub task_manager: (Color, Color),
+    pub brace_match: (Color, Color),
+    pub keyword: Color,
+    pub string: Color,
+    pub number: Color,
+}
+
+// The first theme is the one the kernel starts with.
+pub static THEMES: [Theme; 4] = [
+    Theme{
+        name: "classic",
+        text: (Color::White, Color::Black),
+        highlight: (Color::Black, Color::White),
+        focused_border: Color::White,
+        unfocused_border: Color::White,
+        selection: (Color::Black, Color::LightGray),
+        locked: Color::LightRed,
+        error: (Color::White, Color::Red),
+        status: (Color::Black, Color::LightGray),
+        mode: (Color::White, Color::Blue),
+        task_manager: (Color::White, Color::Black),
+        brace_match: (Color::Black, Color::LightCyan),
+        keyword: Color::LightCyan,
+        string: Color::LightGreen,
+        number: Color::Yellow,
+    },
+    Theme{
+        name: "ocean",
+        text: (Color::White, Color::Blue),
+        highlight: (Color::Blue, Color::LightCyan),
+        focused_border: Color::Yellow,
+        unfocused_border: Color::LightBlue,
+        selection: (Color::Blue, Color::LightGray),
+        locked: Color::Pink,
+        error: (Color::White, Color::Red),
+        status: (Color::White, Color::Cyan),
+        mode: (Color::Blue, Color::Yellow),
+        task_manager: (Color::LightCyan, Color::Black),
+        brace_match: (Color::Black, Color::Yellow),
+        keyword: Color::Yellow,
+        string: Color::LightGreen,
+        number: Color::LightCyan,
+    },
+    Theme{
+        name: "amber",
+        text: (Color::Yellow, Color::Black),
+        highlight: (Color::Black, Color::Yellow),
+        focused_border: Color::Yellow,
+        unfocused_border: Color::Brown,
+        selection: (Color::Black, Color::Brown),
+        locked: Color::LightRed,
+        error: (Color::Black, Color::LightRed),
+        status: (Color::Black, Color::Brown),
+        mode: (Color::Black, Color::Yellow),
+        task_manager: (Color::Brown, Color::Black),
+        brace_match: (Color::Black, Color::White),
+        keyword: Color::White,
+        string: Color::Brown,
+        number: Color::LightRed,
+    },
+    Theme{
+        name: "light",
+        text: (Color::Black, Color::LightGray),
+        highlight: (Color::White, Color::Blue),
+        focused_border: Color::Blue,
+        unfocused_border: Color::DarkGray,
+        selection: (Color::Black, Color::LightCyan),
+        locked: Color::Red,
+        error: (Color::White, Color::Red),
+        status: (Color::White, Color::DarkGray),
+        mode: (Color::White, Color::Blue),
+        task_manager: (Color::Black, Color::White),
+        brace_match: (Color::White, Color::Magenta),
+        keyword: Color::Blue,
+        string: Color::Green,
+        number: Color::Magenta,
+    },
+];
@@ -0,0 +1,271 @@
+// Host-side tool for the disk images the kernel keeps its files on.
+//
+// It is a member of the kernel's workspace, built for the host through the
+// alias in .cargo/config.toml:
+//
+//     cargo diskimg create  <image>
+//     cargo diskimg list    <image>
+//     cargo diskimg mkdir   <image> <path>
+//     cargo diskimg add     <image> <host file> [path]
+//     cargo diskimg extract <image> <path> [host file]
+//     cargo diskimg delete  <image> <path>
+//
+// Paths use the kernel's directories, e.g. "labs/sum" or "/hello". File names
+// are unique across the whole disk, as they are in the kernel. mkdir creates
+// every directory on the path that is missing.
+//
+// The image is the kernel's disk block for block, so the tool mounts it with the
+// same FileSystem and changes only the blocks an operation touches.
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process;
+
+use file_system::{BlockDevice, FileSystem};
+
+#[path = "../../../src/limits.rs"]
+#[allow(dead_code)]
+mod limits;
+#[path = "../../../src/directories.rs"]
+#[allow(dead_code)]
+mod directories;
+#[path = "../../../src/attributes.rs"]
+#[allow(dead_code)]
+mod attributes;
+
+use attributes::{Attributes, ATTRIBUTES_FILE, ATTRIBUTES_BYTES};
+use directories::{DirectoryTree, ROOT, TABLE_FILE, TABLE_BYTES, padded_name, valid_filename};
+// The kernel's file system sizes; directories.rs and attributes.rs size their
+// tables from them too.
+use limits::{MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES};
+
+// QEMU sizes a raw disk in whole sectors, so images are rounded up to them.
+const SECTOR_BYTES: usize = 512;
+
+// Blocks read and written in place in the image file. Past the end of a short
+// file reads as zeros, as a freshly truncated disk does.
+struct ImageFile{
+    path: String,
+    file: File,
+}
+
+impl ImageFile{
+    // The FileSystem has no way to pass an I/O error on, so give up here.
+    fn fail(&self, e: io::Error) -> !{
+        eprintln!("diskimg: {}: {e}", self.path);
+        process::exit(1);
+    }
+}
+
+impl BlockDevice<BLOCK_SIZE> for ImageFile{
+    fn read(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]){
+        let mut file = &self.file;
+        buffer.fill(0);
+        let result = file.seek(SeekFrom::Start((block * BLOCK_SIZE) as u64)).and_then(|_| {
+            let mut done = 0;
+            while done < BLOCK_SIZE{
+                match file.read(&mut buffer[done..])?{
+                    0 => break,
+                    n => done += n,
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result{
+            self.fail(e);
+        }
+    }
+
+    fn write(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]){
+        let result = self.file.seek(SeekFrom::Start((block * BLOCK_SIZE) as u64))
+            .and_then(|_| self.file.write_all(buffer));
+        if let Err(e) = result{
+            self.fail(e);
+        }
+    }
+}
+
+type Files = FileSystem<MAX_OPEN, BLOCK_SIZE, NUM_BLOCKS, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_FILES_STORED, MAX_FILENAME_BYTES, ImageFile>;
+
+struct Image{
+    files: Files,
+}
+
+impl Image{
+    // A freshly formatted disk holding only an empty directory table.
+    fn create(path: &str) -> Result<Self, String>{
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)
+            .map_err(|e| format!("{path}: {e}"))?;
+        let bytes = (NUM_BLOCKS * BLOCK_SIZE).div_ceil(SECTOR_BYTES) * SECTOR_BYTES;
+        file.set_len(bytes as u64).map_err(|e| format!("{path}: {e}"))?;
+        let mut image = Self{files: FileSystem::new(ImageFile{path: path.into(), file})};
+        image.save_tree(&DirectoryTree::new())?;
+        Ok(image)
+    }
+
+    fn load(path: &str) -> Result<Self, String>{
+        let file = OpenOptions::new().read(true).write(true).open(path).map_err(|e| format!("{path}: {e}"))?;
+        let files = FileSystem::mount(ImageFile{path: path.into(), file}).map_err(|_| format!("{path} has not been formatted"))?;
+        let mut image = Self{files};
+        if !image.names()?.contains(&padded_name(TABLE_FILE).unwrap()){
+            return Err(format!("{path} has no directory table"));
+        }
+        Ok(image)
+    }
+
+    fn names(&mut self) -> Result<Vec<[u8; MAX_FILENAME_BYTES]>, String>{
+        let (count, names) = self.files.list_directory().map_err(|e| format!("cannot list files: {e:?}"))?;
+        Ok(names[..count].to_vec())
+    }
+
+    fn contents(&mut self, name: &str) -> Result<Vec<u8>, String>{
+        let mut buffer = vec![0; MAX_FILE_BYTES];
+        let fd = self.files.open_read(name).map_err(|e| format!("{name}: {e:?}"))?;
+        let len = self.files.read(fd, &mut buffer);
+        let _ = self.files.close(fd);
+        buffer.truncate(len.map_err(|e| format!("{name}: {e:?}"))?);
+        Ok(buffer)
+    }
+
+    fn store(&mut self, name: &str, contents: &[u8]) -> Result<(), String>{
+        if contents.len() > MAX_FILE_BYTES{
+            return Err(format!("files hold at most {MAX_FILE_BYTES} bytes"));
+        }
+        self.files.replace(name, contents).map_err(|e| format!("{name}: {e:?}"))
+    }
+
+    fn tree(&mut self) -> Result<DirectoryTree, String>{
+        Ok(DirectoryTree::from_bytes(&self.contents(TABLE_FILE)?))
+    }
+
+    fn save_tree(&mut self, tree: &DirectoryTree) -> Result<(), String>{
+        let mut table = [0; TABLE_BYTES];
+        tree.to_bytes(&mut table);
+        self.store(TABLE_FILE, &table)
+    }
+
+    fn attributes(&mut self) -> Result<Attributes, String>{
+        if self.names()?.contains(&padded_name(ATTRIBUTES_FILE).unwrap()){
+            Ok(Attributes::from_bytes(&self.contents(ATTRIBUTES_FILE)?))
+        }
+        else{
+            Ok(Attributes::new())
+        }
+    }
+
+    fn save_attributes(&mut self, attributes: &Attributes) -> Result<(), String>{
+        let mut table = [0; ATTRIBUTES_BYTES];
+        attributes.to_bytes(&mut table);
+        self.store(ATTRIBUTES_FILE, &table)
+    }
+
+    // The name of the file a path names.
+    fn find(&mut self, tree: &DirectoryTree, path: &str) -> Result<[u8; MAX_FILENAME_BYTES], String>{
+        let (dir, leaf) = tree.resolve(ROOT, path).ok_or(format!("{path}: no such directory"))?;
+        let names = self.names()?;
+        padded_name(leaf)
+            .filter(|name| names.contains(name) && tree.parent_of_file(*name) == dir)
+            .ok_or(format!("{path}: no such file"))
+    }
+}
+
+fn name_str(name: &[u8]) -> &str{
+    std::str::from_utf8(name).unwrap_or("?").trim_end_matches('\0')
+}
+
+fn path_of(tree: &DirectoryTree, name: [u8; MAX_FILENAME_BYTES]) -> String{
+    let mut path = String::from(name_str(&name));
+    let mut dir = tree.parent_of_file(name);
+    while dir != ROOT{
+        path = format!("{}/{path}", name_str(&tree.name(dir)));
+        dir = tree.parent(dir);
+    }
+    format!("/{path}")
+}
+
+fn run(args: &[String]) -> Result<(), String>{
+    let usage = "usage: diskimg create|list|mkdir|add|extract|delete <image> ...";
+    let (command, image_path) = match args{
+        [command, image_path, ..] => (command.as_str(), image_path.as_str()),
+        _ => return Err(usage.into()),
+    };
+    let rest = &args[2..];
+    match (command, rest){
+        ("create", []) => Image::create(image_path).map(|_| ()),
+        ("list", []) => {
+            let mut image = Image::load(image_path)?;
+            let tree = image.tree()?;
+            let attributes = image.attributes()?;
+            for name in image.names()?{
+                if name[0] != b'.'{
+                    let len = image.files.file_size(name_str(&name)).map_err(|e| format!("{}: {e:?}", name_str(&name)))?;
+                    let marker = if attributes.is_locked(name) { "RO" } else { "  " };
+                    println!("{len:>6} {marker}  {}", path_of(&tree, name));
+                }
+            }
+            Ok(())
+        }
+        ("mkdir", [path]) => {
+            let mut image = Image::load(image_path)?;
+            let mut tree = image.tree()?;
+            let mut dir = ROOT;
+            for component in path.split('/').filter(|c| !c.is_empty()){
+                let name = padded_name(component).filter(|_| valid_filename(component))
+                    .ok_or(format!("{component}: names are 1-{MAX_FILENAME_BYTES} of a-z 0-9 _ - ."))?;
+                dir = match tree.find_child(dir, name){
+                    Some(child) => child,
+                    None => tree.create(dir, name).ok_or("the directory table is full")?,
+                };
+            }
+            image.save_tree(&tree)
+        }
+        ("add", [host_file, ..]) if rest.len() <= 2 => {
+            let mut image = Image::load(image_path)?;
+            let mut tree = image.tree()?;
+            let path = rest.get(1).map(String::as_str)
+                .unwrap_or_else(|| host_file.rsplit('/').next().unwrap());
+            let (dir, leaf) = tree.resolve(ROOT, path).ok_or(format!("{path}: no such directory"))?;
+            let name = padded_name(leaf).filter(|_| valid_filename(leaf))
+                .ok_or(format!("{leaf}: names are 1-{MAX_FILENAME_BYTES} of a-z 0-9 _ - ."))?;
+            if image.names()?.contains(&name){
+                return Err(format!("{leaf} already exists at {}", path_of(&tree, name)));
+            }
+            let contents = std::fs::read(host_file).map_err(|e| format!("{host_file}: {e}"))?;
+            // Claim the table entry first so a full table leaves the image untouched.
+            if !tree.set_file_parent(name, dir){
+                return Err("the directory table is full".into());
+            }
+            image.store(leaf, &contents)?;
+            image.save_tree(&tree)
+        }
+        ("extract", [path, ..]) if rest.len() <= 2 => {
+            let mut image = Image::load(image_path)?;
+            let tree = image.tree()?;
+            let name = image.find(&tree, path)?;
+            let host_file = rest.get(1).cloned().unwrap_or_else(|| name_str(&name).into());
+            let contents = image.contents(name_str(&name))?;
+            std::fs::write(&host_file, contents).map_err(|e| format!("{host_file}: {e}"))
+        }
+        ("delete", [path]) => {
+            let mut image = Image::load(image_path)?;
+            let mut tree = image.tree()?;
+            let name = image.find(&tree, path)?;
+            let mut attributes = image.attributes()?;
+            image.files.unlink(name_str(&name)).map_err(|e| format!("{path}: {e:?}"))?;
+            tree.forget_file(name);
+            attributes.forget_file(name);
+            image.save_tree(&tree)?;
+            image.save_attributes(&attributes)
+        }
+        _ => Err(usage.into()),
+    }
+}
+
+fn main(){
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(message) = run(&args){
+        eprintln!("diskimg: {message}");
+        process::exit(1);
+    }
+}